@@ -0,0 +1,8 @@
+ALTER TABLE schedules DROP COLUMN difficulty;
+ALTER TABLE schedules DROP COLUMN stability;
+ALTER TABLE schedules DROP COLUMN repetitions;
+ALTER TABLE schedules DROP COLUMN interval_secs;
+ALTER TABLE schedules DROP COLUMN ease_factor;
+
+ALTER TABLE memories DROP COLUMN algorithm;
+ALTER TABLE users DROP COLUMN algorithm;
@@ -0,0 +1,8 @@
+ALTER TABLE users ADD COLUMN algorithm VARCHAR NOT NULL DEFAULT 'fixed';
+ALTER TABLE memories ADD COLUMN algorithm VARCHAR;
+
+ALTER TABLE schedules ADD COLUMN ease_factor DOUBLE PRECISION NOT NULL DEFAULT 2.5;
+ALTER TABLE schedules ADD COLUMN interval_secs BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE schedules ADD COLUMN repetitions INT NOT NULL DEFAULT 0;
+ALTER TABLE schedules ADD COLUMN stability DOUBLE PRECISION;
+ALTER TABLE schedules ADD COLUMN difficulty DOUBLE PRECISION;
@@ -0,0 +1,11 @@
+CREATE TABLE reviews (
+  id SERIAL PRIMARY KEY,
+  schedule_id INT references schedules(id) NOT NULL,
+  phase_number INT NOT NULL,
+  sent_at BIGINT NOT NULL,
+  channel VARCHAR NOT NULL,
+  delivery_status VARCHAR NOT NULL,
+  grade VARCHAR,
+  response_latency BIGINT
+);
+CREATE INDEX reviews_schedule_id_idx ON reviews(schedule_id);
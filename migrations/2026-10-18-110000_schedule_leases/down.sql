@@ -0,0 +1,3 @@
+DROP INDEX schedules_next_run_idx;
+ALTER TABLE schedules DROP COLUMN locked_until;
+ALTER TABLE schedules DROP COLUMN locked_by;
@@ -0,0 +1,3 @@
+ALTER TABLE schedules ADD COLUMN locked_by VARCHAR;
+ALTER TABLE schedules ADD COLUMN locked_until BIGINT;
+CREATE INDEX schedules_next_run_idx ON schedules(next_run);
@@ -0,0 +1,3 @@
+ALTER TABLE schedules DROP COLUMN last_error;
+ALTER TABLE schedules DROP COLUMN attempts;
+ALTER TABLE schedules DROP COLUMN status;
@@ -0,0 +1,3 @@
+ALTER TABLE schedules ADD COLUMN status VARCHAR NOT NULL DEFAULT 'active';
+ALTER TABLE schedules ADD COLUMN attempts INT NOT NULL DEFAULT 0;
+ALTER TABLE schedules ADD COLUMN last_error TEXT;
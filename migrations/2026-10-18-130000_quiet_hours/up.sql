@@ -0,0 +1,3 @@
+ALTER TABLE users ADD COLUMN timezone VARCHAR NOT NULL DEFAULT 'UTC';
+ALTER TABLE users ADD COLUMN quiet_start TIME;
+ALTER TABLE users ADD COLUMN quiet_end TIME;
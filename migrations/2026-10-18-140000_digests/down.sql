@@ -0,0 +1,2 @@
+ALTER TABLE users DROP COLUMN digest_time;
+ALTER TABLE users DROP COLUMN delivery_mode;
@@ -0,0 +1,2 @@
+ALTER TABLE users ADD COLUMN delivery_mode VARCHAR NOT NULL DEFAULT 'immediate';
+ALTER TABLE users ADD COLUMN digest_time TIME;
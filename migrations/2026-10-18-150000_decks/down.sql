@@ -0,0 +1,2 @@
+ALTER TABLE memories DROP COLUMN deck_id;
+DROP TABLE decks;
@@ -0,0 +1,10 @@
+CREATE TABLE decks (
+  id SERIAL PRIMARY KEY,
+  user_id INT references users(id) NOT NULL,
+  name VARCHAR NOT NULL,
+  phase_set VARCHAR,
+  notifications_enabled BOOLEAN NOT NULL DEFAULT TRUE,
+  UNIQUE (user_id, name)
+);
+ALTER TABLE memories ADD COLUMN deck_id INT references decks(id) ON DELETE SET NULL;
+CREATE INDEX memories_deck_id_idx ON memories(deck_id);
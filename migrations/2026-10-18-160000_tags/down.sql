@@ -0,0 +1,2 @@
+DROP TABLE memory_tags;
+DROP TABLE tags;
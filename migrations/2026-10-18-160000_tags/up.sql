@@ -0,0 +1,12 @@
+CREATE TABLE tags (
+  id SERIAL PRIMARY KEY,
+  user_id INT references users(id) NOT NULL,
+  name VARCHAR NOT NULL,
+  UNIQUE (user_id, name)
+);
+CREATE TABLE memory_tags (
+  memory_id INT references memories(id) NOT NULL,
+  tag_id INT references tags(id) NOT NULL,
+  PRIMARY KEY (memory_id, tag_id)
+);
+CREATE INDEX memory_tags_tag_id_idx ON memory_tags(tag_id);
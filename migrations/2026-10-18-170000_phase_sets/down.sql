@@ -0,0 +1,6 @@
+DELETE FROM phases WHERE phase_set <> 'default';
+ALTER TABLE users DROP COLUMN phase_set;
+ALTER TABLE phases DROP CONSTRAINT phases_phase_set_phase_number_key;
+ALTER TABLE phases DROP COLUMN phase_set;
+ALTER TABLE phases ADD CONSTRAINT phases_phase_number_key UNIQUE (phase_number);
+ALTER TABLE schedules ADD CONSTRAINT schedules_phase_number_fkey FOREIGN KEY (phase_number) REFERENCES phases(phase_number);
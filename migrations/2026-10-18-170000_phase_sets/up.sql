@@ -0,0 +1,21 @@
+ALTER TABLE schedules DROP CONSTRAINT schedules_phase_number_fkey;
+ALTER TABLE phases DROP CONSTRAINT phases_phase_number_key;
+ALTER TABLE phases ADD COLUMN phase_set VARCHAR NOT NULL DEFAULT 'default';
+ALTER TABLE phases ADD CONSTRAINT phases_phase_set_phase_number_key UNIQUE (phase_set, phase_number);
+ALTER TABLE users ADD COLUMN phase_set VARCHAR;
+
+INSERT INTO phases(phase_set, phase_number, seconds_to_wait) VALUES('cram', 1, 0);
+INSERT INTO phases(phase_set, phase_number, seconds_to_wait) VALUES('cram', 2, 20*60);
+INSERT INTO phases(phase_set, phase_number, seconds_to_wait) VALUES('cram', 3, 3*60*60);
+INSERT INTO phases(phase_set, phase_number, seconds_to_wait) VALUES('cram', 4, 12*60*60);
+INSERT INTO phases(phase_set, phase_number, seconds_to_wait) VALUES('cram', 5, 24*60*60);
+INSERT INTO phases(phase_set, phase_number, seconds_to_wait) VALUES('cram', 6, 24*60*60);
+
+INSERT INTO phases(phase_set, phase_number, seconds_to_wait) VALUES('vocab', 1, 0);
+INSERT INTO phases(phase_set, phase_number, seconds_to_wait) VALUES('vocab', 2, 10*60);
+INSERT INTO phases(phase_set, phase_number, seconds_to_wait) VALUES('vocab', 3, 24*60*60);
+INSERT INTO phases(phase_set, phase_number, seconds_to_wait) VALUES('vocab', 4, 3*24*60*60);
+INSERT INTO phases(phase_set, phase_number, seconds_to_wait) VALUES('vocab', 5, 7*24*60*60);
+INSERT INTO phases(phase_set, phase_number, seconds_to_wait) VALUES('vocab', 6, 16*24*60*60);
+INSERT INTO phases(phase_set, phase_number, seconds_to_wait) VALUES('vocab', 7, 35*24*60*60);
+INSERT INTO phases(phase_set, phase_number, seconds_to_wait) VALUES('vocab', 8, 75*24*60*60);
@@ -0,0 +1,7 @@
+CREATE TABLE api_keys (
+  id SERIAL PRIMARY KEY,
+  user_id INT references users(id) NOT NULL,
+  key_hash VARCHAR UNIQUE NOT NULL,
+  created_at BIGINT NOT NULL DEFAULT extract(epoch from now())::BIGINT
+);
+CREATE INDEX api_keys_user_id_idx ON api_keys(user_id);
@@ -0,0 +1,2 @@
+DROP TABLE verification_tokens;
+ALTER TABLE users DROP COLUMN verified;
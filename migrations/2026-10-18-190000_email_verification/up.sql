@@ -0,0 +1,9 @@
+-- users created before verification keep receiving reminders
+ALTER TABLE users ADD COLUMN verified BOOLEAN NOT NULL DEFAULT true;
+ALTER TABLE users ALTER COLUMN verified SET DEFAULT false;
+
+CREATE TABLE verification_tokens (
+  user_id INT PRIMARY KEY references users(id),
+  token_hash VARCHAR UNIQUE NOT NULL,
+  created_at BIGINT NOT NULL DEFAULT extract(epoch from now())::BIGINT
+);
@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN unsubscribed BOOLEAN NOT NULL DEFAULT false;
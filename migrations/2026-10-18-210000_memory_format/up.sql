@@ -0,0 +1 @@
+ALTER TABLE memories ADD COLUMN format VARCHAR NOT NULL DEFAULT 'plain';
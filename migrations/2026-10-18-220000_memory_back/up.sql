@@ -0,0 +1 @@
+ALTER TABLE memories ADD COLUMN back TEXT;
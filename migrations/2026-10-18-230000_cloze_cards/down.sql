@@ -0,0 +1,2 @@
+DROP INDEX schedules_memory_card_idx;
+ALTER TABLE schedules DROP COLUMN card;
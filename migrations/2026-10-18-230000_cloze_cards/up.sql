@@ -0,0 +1,3 @@
+-- card 0 is the whole memory, cloze cards are numbered by their cloze index
+ALTER TABLE schedules ADD COLUMN card INT NOT NULL DEFAULT 0;
+CREATE UNIQUE INDEX schedules_memory_card_idx ON schedules(memory_id, card);
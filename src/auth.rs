@@ -0,0 +1,193 @@
+use crate::db_actions;
+use crate::models::User;
+use crate::DbPool;
+use actix_web::dev::Payload;
+use actix_web::http::header;
+use actix_web::{web, Error, FromRequest, HttpRequest, HttpResponse};
+use futures::future::{self, FutureExt, LocalBoxFuture, Ready};
+use log::error;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::env;
+
+/// Generates a new API key or verification token, returns the secret to hand out to user and its hash to store.
+pub fn new_secret() -> (String, String) {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let key = hex::encode(bytes);
+    let hash = hash_secret(&key);
+    (key, hash)
+}
+
+pub fn hash_secret(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+fn bearer_key(req: &HttpRequest) -> Option<String> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let key = value.strip_prefix("Bearer ")?.trim();
+    if key.is_empty() {
+        None
+    } else {
+        Some(key.to_string())
+    }
+}
+
+fn unauthorized() -> Error {
+    HttpResponse::Unauthorized()
+        .header(header::WWW_AUTHENTICATE, "Bearer")
+        .finish()
+        .into()
+}
+
+/// Fails with 403 unless `owner_id` is id of user making the request.
+pub fn check_owner(user: &User, owner_id: i32) -> Result<(), HttpResponse> {
+    if user.id == owner_id {
+        Ok(())
+    } else {
+        Err(HttpResponse::Forbidden().finish())
+    }
+}
+
+/// User making the request, resolved from `Authorization: Bearer <api key>` header.
+/// Requests without a key or with an unknown one are rejected with 401.
+pub struct AuthUser(pub User);
+
+impl FromRequest for AuthUser {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<AuthUser, Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let key = bearer_key(req);
+        let pool = req.app_data::<web::Data<DbPool>>().cloned();
+
+        async move {
+            let key = key.ok_or_else(unauthorized)?;
+            let pool = pool.ok_or_else(|| {
+                error!("no db pool to authenticate request");
+                Error::from(HttpResponse::InternalServerError().finish())
+            })?;
+            let conn = pool.get().expect("couldn't get db connection from pool");
+            let user =
+                web::block(move || db_actions::get_user_by_key_hash(&hash_secret(&key), &conn))
+                    .await
+                    .map_err(|e| {
+                        error!("{}", e);
+                        Error::from(HttpResponse::InternalServerError().finish())
+                    })?;
+
+            user.map(AuthUser).ok_or_else(unauthorized)
+        }
+        .boxed_local()
+    }
+}
+
+/// Request made with `ADMIN_API_KEY`, admin endpoints are closed when it is not set.
+pub struct Admin;
+
+impl FromRequest for Admin {
+    type Error = Error;
+    type Future = Ready<Result<Admin, Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let admin_key = match env::var("ADMIN_API_KEY") {
+            Ok(key) if !key.is_empty() => key,
+            _ => return future::err(HttpResponse::Forbidden().finish().into()),
+        };
+
+        // hashes are compared so comparison time doesn't depend on how much of the key matches
+        match bearer_key(req) {
+            Some(key) if hash_secret(&key) == hash_secret(&admin_key) => future::ok(Admin),
+            _ => future::err(unauthorized()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_hash_of_secret_is_kept() {
+        let (key, hash) = new_secret();
+        assert_eq!(key.len(), 64);
+        assert_ne!(key, hash);
+        assert_eq!(hash_secret(&key), hash);
+        assert_ne!(new_secret().0, key);
+    }
+
+    #[actix_rt::test]
+    #[ignore]
+    async fn only_owner_gets_its_resources() {
+        use crate::handlers::memories;
+        use crate::test_db;
+        use actix_web::http::StatusCode;
+        use actix_web::{test, App};
+
+        let pool = test_db::pool();
+        let (owner_id, owner_key, other_key, memory_id) = {
+            let conn = pool.get().unwrap();
+            let (owner_id, owner_key) = test_db::user("owner@example.com", &conn);
+            let (_, other_key) = test_db::user("other@example.com", &conn);
+            let memory_id = test_db::memory(owner_id, "ownership", &conn);
+            (owner_id, owner_key, other_key, memory_id)
+        };
+        let mut app = test::init_service(
+            App::new()
+                .data(pool)
+                .service(memories::get_memory)
+                .service(memories::get_user_memories),
+        )
+        .await;
+
+        let bearer = |key: &str| format!("Bearer {}", key);
+        let cases = vec![
+            (
+                format!("/memories/{}", memory_id),
+                None,
+                StatusCode::UNAUTHORIZED,
+            ),
+            (
+                format!("/memories/{}", memory_id),
+                Some(bearer("unknown")),
+                StatusCode::UNAUTHORIZED,
+            ),
+            (
+                format!("/memories/{}", memory_id),
+                Some(bearer(&other_key)),
+                StatusCode::FORBIDDEN,
+            ),
+            (
+                format!("/memories/{}", memory_id),
+                Some(bearer(&owner_key)),
+                StatusCode::OK,
+            ),
+            (
+                format!("/users/{}/memories", owner_id),
+                Some(bearer(&other_key)),
+                StatusCode::FORBIDDEN,
+            ),
+            (
+                format!("/users/{}/memories", owner_id),
+                Some(bearer(&owner_key)),
+                StatusCode::OK,
+            ),
+        ];
+        for (uri, authorization, status) in cases {
+            let mut req = test::TestRequest::get().uri(&uri);
+            if let Some(value) = &authorization {
+                req = req.header(header::AUTHORIZATION, value.as_str());
+            }
+            let resp = test::call_service(&mut app, req.to_request()).await;
+            assert_eq!(resp.status(), status, "{} with {:?}", uri, authorization);
+            if status == StatusCode::UNAUTHORIZED {
+                assert_eq!(
+                    resp.headers().get(header::WWW_AUTHENTICATE).unwrap(),
+                    "Bearer"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use crate::models::Memory;
+use std::collections::BTreeSet;
+
+const CLOZE_START: &str = "{{c";
+const CLOZE_END: &str = "}}";
+
+/// Card of a memory without clozes.
+pub const WHOLE_MEMORY: i32 = 0;
+
+/// Text shown instead of a hidden cloze without hint.
+const BLANK: &str = "[...]";
+
+/// Cloze deletion `{{c1::answer}}` or `{{c1::answer::hint}}` in memory text.
+#[derive(Debug, PartialEq)]
+pub struct Cloze<'a> {
+    pub index: u32,
+    pub answer: &'a str,
+    pub hint: Option<&'a str>,
+    start: usize,
+    end: usize,
+}
+
+/// Clozes of `text` in order of appearance, malformed ones are left as plain text.
+pub fn clozes(text: &str) -> Vec<Cloze<'_>> {
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(offset) = text[from..].find(CLOZE_START) {
+        let start = from + offset;
+        from = start + CLOZE_START.len();
+        if let Some(cloze) = parse_cloze(text, start) {
+            from = cloze.end;
+            found.push(cloze);
+        }
+    }
+    found
+}
+
+fn parse_cloze(text: &str, start: usize) -> Option<Cloze<'_>> {
+    let rest = &text[start + CLOZE_START.len()..];
+    let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+    let index = rest[..digits].parse::<u32>().ok().filter(|i| *i > 0)?;
+    let body = rest[digits..].strip_prefix("::")?;
+    let body_len = body.find(CLOZE_END)?;
+    let mut parts = body[..body_len].splitn(2, "::");
+    let answer = parts.next()?;
+    if answer.is_empty() {
+        return None;
+    }
+
+    Some(Cloze {
+        index,
+        answer,
+        hint: parts.next(),
+        end: start + CLOZE_START.len() + digits + "::".len() + body_len + CLOZE_END.len(),
+        start,
+    })
+}
+
+/// `text` with clozes replaced by what `show` returns for them.
+fn replace_clozes<F: Fn(&Cloze) -> String>(text: &str, show: F) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut from = 0;
+    for cloze in clozes(text) {
+        result.push_str(&text[from..cloze.start]);
+        result.push_str(&show(&cloze));
+        from = cloze.end;
+    }
+    result.push_str(&text[from..]);
+    result
+}
+
+/// Memory hides an answer when it has a back side or cloze deletions.
+pub fn is_card(memory: &Memory) -> bool {
+    memory.back.is_some() || !clozes(&memory.text).is_empty()
+}
+
+/// Cards scheduled separately: one per cloze index, or the whole memory when it has no clozes.
+pub fn card_numbers(memory: &Memory) -> BTreeSet<i32> {
+    text_card_numbers(&memory.text)
+}
+
+/// Same as `card_numbers` for text of a memory that isn't saved yet.
+pub fn text_card_numbers(text: &str) -> BTreeSet<i32> {
+    let numbers: BTreeSet<i32> = clozes(text)
+        .iter()
+        .map(|cloze| cloze.index as i32)
+        .collect();
+    if numbers.is_empty() {
+        std::iter::once(WHOLE_MEMORY).collect()
+    } else {
+        numbers
+    }
+}
+
+/// What a reminder about `card` shows: text with clozes of the card hidden and the other ones filled in.
+/// All clozes are hidden on the whole memory card.
+pub fn front(memory: &Memory, card: i32) -> String {
+    replace_clozes(&memory.text, |cloze| {
+        if card != WHOLE_MEMORY && cloze.index as i32 != card {
+            return cloze.answer.to_string();
+        }
+        match cloze.hint {
+            Some(hint) => format!("[{}]", hint),
+            None => BLANK.to_string(),
+        }
+    })
+}
+
+/// What is shown after the answer is revealed: text with clozes filled in and the back side.
+pub fn back(memory: &Memory) -> String {
+    let has_clozes = !clozes(&memory.text).is_empty();
+    let filled = replace_clozes(&memory.text, |cloze| cloze.answer.to_string());
+    match (&memory.back, has_clozes) {
+        (Some(back), true) => format!("{}\n\n{}", filled, back),
+        (Some(back), false) => back.clone(),
+        (None, _) => filled,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory(text: &str, back: Option<&str>) -> Memory {
+        Memory {
+            id: 1,
+            user_id: 1,
+            topic: None,
+            text: text.to_string(),
+            algorithm: None,
+            deck_id: None,
+            format: "plain".to_string(),
+            back: back.map(|b| b.to_string()),
+        }
+    }
+
+    #[test]
+    fn parses_clozes_with_and_without_hints() {
+        let found = clozes("{{c1::Rust}} has {{c2::ownership::what?}} and {{c::bad}} {{c3::}}");
+        assert_eq!(found.len(), 2);
+        assert_eq!(
+            (found[0].index, found[0].answer, found[0].hint),
+            (1, "Rust", None)
+        );
+        assert_eq!(
+            (found[1].index, found[1].answer, found[1].hint),
+            (2, "ownership", Some("what?"))
+        );
+    }
+
+    #[test]
+    fn cloze_is_hidden_on_front_and_filled_on_back() {
+        let card = memory("{{c1::Rust}} checks {{c2::borrows::what?}}", None);
+        assert!(is_card(&card));
+        assert_eq!(front(&card, WHOLE_MEMORY), "[...] checks [what?]");
+        assert_eq!(front(&card, 1), "[...] checks borrows");
+        assert_eq!(front(&card, 2), "Rust checks [what?]");
+        assert_eq!(back(&card), "Rust checks borrows");
+    }
+
+    #[test]
+    fn front_and_back_sides() {
+        let card = memory("Who owns a moved value?", Some("The new binding"));
+        assert!(is_card(&card));
+        assert_eq!(front(&card, WHOLE_MEMORY), "Who owns a moved value?");
+        assert_eq!(back(&card), "The new binding");
+        assert!(!is_card(&memory("Just a note", None)));
+    }
+
+    #[test]
+    fn one_card_per_cloze_index() {
+        let cloze = memory("{{c2::a}} {{c1::b}} {{c2::c}}", None);
+        assert_eq!(card_numbers(&cloze), [1, 2].iter().cloned().collect());
+        let plain = memory("no clozes", Some("back"));
+        assert_eq!(
+            card_numbers(&plain),
+            [WHOLE_MEMORY].iter().cloned().collect()
+        );
+    }
+}
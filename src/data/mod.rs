@@ -1,23 +1,292 @@
+use crate::delivery::DeliveryMode;
+use crate::export::ExportFormat;
+use crate::import::ImportFormat;
+use crate::markdown::TextFormat;
+use crate::models::{Memory, MemoryWithSchedules, Schedule};
+use crate::scheduling::{AlgorithmKind, Grade};
+use chrono::NaiveTime;
+use serde::{Deserialize, Deserializer};
 use std::fmt;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateUserRequest {
     pub email: String,
+    pub algorithm: Option<AlgorithmKind>,
+    pub timezone: Option<String>,
+    pub quiet_hours: Option<QuietHoursRequest>,
+    pub delivery_mode: Option<DeliveryMode>,
+    pub digest_time: Option<NaiveTime>,
+    pub phase_set: Option<String>,
+}
+
+/// Local time range when reminders should not be delivered, e.g. from "22:00:00" to "07:00:00".
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QuietHoursRequest {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateUserRequest {
+    pub timezone: Option<String>,
+    /// `null` turns quiet hours off, missing field keeps them as is.
+    #[serde(default, deserialize_with = "double_option")]
+    pub quiet_hours: Option<Option<QuietHoursRequest>>,
+    pub delivery_mode: Option<DeliveryMode>,
+    /// Local time of daily digest, `null` resets it to default 09:00.
+    #[serde(default, deserialize_with = "double_option")]
+    pub digest_time: Option<Option<NaiveTime>>,
+    /// `null` makes user's memories use the default phase set.
+    #[serde(default, deserialize_with = "double_option")]
+    pub phase_set: Option<Option<String>>,
+    /// `false` turns reminders back on after unsubscribe.
+    pub unsubscribed: Option<bool>,
+}
+
+fn double_option<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Deserialize::deserialize(deserializer).map(Some)
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateUserResponse {
     pub user_id: i32,
+    /// Shown only once, requests of the user must carry it in `Authorization: Bearer` header.
+    pub api_key: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiKeyResponse {
+    pub api_key: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateMemoryRequest {
     pub user_id: i32,
     pub topic: Option<String>,
+    /// Front side of a card, may contain cloze deletions like `{{c1::answer}}`.
+    #[serde(alias = "front")]
     pub text: String,
+    /// Answer that reminders hide until it is revealed.
+    pub back: Option<String>,
+    pub algorithm: Option<AlgorithmKind>,
+    pub deck_id: Option<i32>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// `plain` by default.
+    pub format: Option<TextFormat>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateMemoryResponse {
     pub memory_id: i32,
 }
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReviewRequest {
+    pub grade: Grade,
+    /// Cloze index of the graded card, the card reminded last by default.
+    pub card: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReviewResponse {
+    pub memory_id: i32,
+    pub card: i32,
+    pub phase_number: i32,
+    pub next_run: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PageQuery {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+impl PageQuery {
+    pub const DEFAULT_PER_PAGE: i64 = 20;
+    pub const MAX_PER_PAGE: i64 = 100;
+    /// Keeps offset of the page far from overflow.
+    pub const MAX_PAGE: i64 = 1_000_000;
+
+    /// 1-based page number and page size clamped to sane bounds.
+    pub fn bounds(&self) -> (i64, i64) {
+        let page = self.page.unwrap_or(1).clamp(1, PageQuery::MAX_PAGE);
+        let per_page = self
+            .per_page
+            .unwrap_or(PageQuery::DEFAULT_PER_PAGE)
+            .clamp(1, PageQuery::MAX_PER_PAGE);
+        (page, per_page)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MemoriesPage {
+    pub memories: Vec<MemoryResponse>,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+}
+
+/// Filters of user's memories listing.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MemoryFilter {
+    pub deck_id: Option<i32>,
+    pub tag: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateMemoryRequest {
+    pub topic: Option<String>,
+    #[serde(alias = "front")]
+    pub text: Option<String>,
+    /// `null` removes the back side.
+    #[serde(default, deserialize_with = "double_option")]
+    pub back: Option<Option<String>>,
+    /// `null` takes memory out of its deck, missing field keeps it in the current one.
+    #[serde(default, deserialize_with = "double_option")]
+    pub deck_id: Option<Option<i32>>,
+    /// Replaces all tags of memory.
+    pub tags: Option<Vec<String>>,
+    pub format: Option<TextFormat>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImportQuery {
+    /// `csv` by default.
+    pub format: Option<ImportFormat>,
+    /// Deck to put all memories imported from CSV or Anki file into.
+    pub deck_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExportQuery {
+    /// `json` by default.
+    pub format: Option<ExportFormat>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImportResponse {
+    pub memory_ids: Vec<i32>,
+    /// Rows that were not imported.
+    pub errors: Vec<ImportRowError>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImportRowError {
+    /// Line of CSV or Anki file where the row starts, position of memory in JSON export counting from 1.
+    pub row: u64,
+    pub error: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateDeckRequest {
+    pub user_id: i32,
+    pub name: String,
+    pub phase_set: Option<String>,
+    pub notifications_enabled: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateDeckRequest {
+    pub name: Option<String>,
+    /// `null` makes deck use phase set of its owner.
+    #[serde(default, deserialize_with = "double_option")]
+    pub phase_set: Option<Option<String>>,
+    pub notifications_enabled: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScheduleResponse {
+    pub card: i32,
+    pub status: String,
+    pub phase_number: i32,
+    pub next_run: Option<i64>,
+}
+
+impl From<Schedule> for ScheduleResponse {
+    fn from(schedule: Schedule) -> ScheduleResponse {
+        ScheduleResponse {
+            card: schedule.card,
+            status: schedule.status,
+            phase_number: schedule.phase_number,
+            next_run: schedule.next_run,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MemoryResponse {
+    #[serde(flatten)]
+    pub memory: Memory,
+    pub tags: Vec<String>,
+    /// Card that is due first.
+    pub schedule: Option<ScheduleResponse>,
+    /// Separately scheduled cards, one per cloze index.
+    pub cards: Vec<ScheduleResponse>,
+}
+
+impl MemoryResponse {
+    pub fn new(memory_with_schedules: MemoryWithSchedules, tags: Vec<String>) -> MemoryResponse {
+        let cards: Vec<ScheduleResponse> = memory_with_schedules
+            .schedules
+            .into_iter()
+            .map(ScheduleResponse::from)
+            .collect();
+        // cards without next run are done and come last
+        let schedule = cards
+            .iter()
+            .min_by_key(|c| (c.next_run.is_none(), c.next_run))
+            .cloned();
+
+        MemoryResponse {
+            memory: memory_with_schedules.memory,
+            tags,
+            schedule,
+            cards,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TaggedChangeResponse {
+    /// How many memories with the tag were changed.
+    pub changed: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PhaseSetSummary {
+    pub name: String,
+    pub phases: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PhaseRequest {
+    pub number: i32,
+    pub seconds_to_wait: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReplacePhasesRequest {
+    /// Set to replace, the default one when missing, new set is created when there is no such set.
+    pub phase_set: Option<String>,
+    pub phases: Vec<PhaseRequest>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_bounds_are_clamped() {
+        let bounds = |page, per_page| PageQuery { page, per_page }.bounds();
+        assert_eq!(bounds(None, None), (1, PageQuery::DEFAULT_PER_PAGE));
+        assert_eq!(bounds(Some(-3), Some(1000)), (1, PageQuery::MAX_PER_PAGE));
+        let (page, per_page) = bounds(Some(i64::MAX), Some(i64::MAX));
+        assert_eq!(page, PageQuery::MAX_PAGE);
+        assert!((page - 1).checked_mul(per_page).is_some());
+    }
+}
@@ -1,57 +1,355 @@
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 
+use crate::cards;
+use crate::delivery;
+use crate::export::{Export, ExportedMemory};
+use crate::markdown::TextFormat;
 use crate::models;
 use crate::phase::*;
+use crate::scheduling::{self, AlgorithmKind, Grade, ScheduleState};
+use std::collections::{BTreeMap, BTreeSet};
 use std::time::SystemTime;
 
-pub fn get_phases(conn: &PgConnection) -> Result<Phases, failure::Error> {
+pub fn get_phase_sets(conn: &PgConnection) -> Result<PhaseSets, failure::Error> {
     use crate::schema::phases::dsl::*;
 
     let all_phases: Vec<models::Phase> = phases
         .load::<models::Phase>(conn)
         .map_err(|_| PhaseError::DbError)?;
 
-    Ok(Phases::new(all_phases)?)
+    Ok(PhaseSets::new(all_phases)?)
 }
 
-pub fn insert_user(user_email: &str, conn: &PgConnection) -> Result<i32, diesel::result::Error> {
-    use crate::schema::users::dsl::*;
+pub fn get_all_phases(conn: &PgConnection) -> Result<Vec<models::Phase>, diesel::result::Error> {
+    use crate::schema::phases::dsl::*;
 
-    let new_user = models::NewUser { email: user_email };
-    let result = diesel::insert_into(users)
-        .values(&new_user)
-        .get_result::<models::User>(conn)?;
+    phases
+        .order((phase_set.asc(), phase_number.asc()))
+        .load::<models::Phase>(conn)
+}
 
-    Ok(result.id)
+#[derive(Debug, Fail)]
+#[fail(
+    display = "{} schedules of phase set '{}' are on phases which would be removed",
+    count, phase_set
+)]
+pub struct OrphanedSchedules {
+    pub phase_set: String,
+    pub count: usize,
 }
 
-pub fn get_schedulers(
+/// Replaces phases of `set_name` with `new_phases` (number and seconds to wait) in one transaction.
+/// Nothing is changed when any phase set becomes invalid or schedules of this set are left on removed phases.
+pub fn replace_phase_set(
+    set_name: &str,
+    new_phases: &[(i32, i64)],
+    conn: &PgConnection,
+) -> Result<PhaseSets, failure::Error> {
+    use crate::schema::phases::dsl::*;
+    use crate::schema::{decks, memories, schedules, users};
+
+    conn.transaction::<PhaseSets, failure::Error, _>(|| {
+        diesel::delete(phases.filter(phase_set.eq(set_name))).execute(conn)?;
+        let rows: Vec<models::NewPhase> = new_phases
+            .iter()
+            .map(|(number, wait)| models::NewPhase {
+                phase_number: *number,
+                seconds_to_wait: *wait,
+                phase_set: set_name,
+            })
+            .collect();
+        diesel::insert_into(phases).values(&rows).execute(conn)?;
+        let new_sets = get_phase_sets(conn)?;
+
+        let numbers: Vec<i32> = new_phases.iter().map(|(number, _)| *number).collect();
+        let off_phase = schedules::table
+            .inner_join(
+                memories::table
+                    .inner_join(users::table)
+                    .left_join(decks::table),
+            )
+            .filter(schedules::phase_number.ne_all(numbers))
+            .filter(schedules::status.ne(models::ScheduleStatus::Retired.as_str()))
+            .into_boxed();
+        // same choice as `PhaseSets::name_for_memory`: deck's set, else user's one, else the default set,
+        // which also takes memories of unknown sets
+        let off_phase = if set_name == DEFAULT_PHASE_SET {
+            let other_sets: Vec<&str> = new_sets
+                .iter()
+                .map(|(name, _)| name)
+                .filter(|name| *name != DEFAULT_PHASE_SET)
+                .collect();
+            off_phase.filter(
+                decks::phase_set
+                    .ne_all(other_sets.clone())
+                    .or(decks::phase_set.is_null().and(
+                        users::phase_set
+                            .ne_all(other_sets)
+                            .or(users::phase_set.is_null()),
+                    )),
+            )
+        } else {
+            off_phase.filter(
+                decks::phase_set.eq(set_name).or(decks::phase_set
+                    .is_null()
+                    .and(users::phase_set.eq(set_name))),
+            )
+        };
+        let orphaned = off_phase.count().get_result::<i64>(conn)? as usize;
+        if orphaned > 0 {
+            return Err(OrphanedSchedules {
+                phase_set: set_name.to_string(),
+                count: orphaned,
+            }
+            .into());
+        }
+
+        Ok(new_sets)
+    })
+}
+
+/// Creates user together with API key which hash is `key_hash`.
+pub fn insert_user(
+    new_user: &models::NewUser,
+    key_hash: &str,
+    conn: &PgConnection,
+) -> Result<i32, diesel::result::Error> {
+    use crate::schema::{api_keys, users};
+
+    conn.transaction(|| {
+        let result = diesel::insert_into(users::table)
+            .values(new_user)
+            .get_result::<models::User>(conn)?;
+        diesel::insert_into(api_keys::table)
+            .values(&models::NewApiKey {
+                user_id: result.id,
+                key_hash,
+            })
+            .execute(conn)?;
+
+        Ok(result.id)
+    })
+}
+
+/// Leases up to `limit` due schedules to `owner` until `lease_until`, so other service instances skip them.
+/// Schedules with expired lease are due again.
+pub fn claim_schedulers(
     at_secs: i64,
+    owner: &str,
+    lease_until: i64,
+    limit: i64,
     conn: &PgConnection,
 ) -> Result<Vec<models::ScheduleWithMemoryAndUser>, diesel::result::Error> {
-    use crate::schema::memories;
     use crate::schema::schedules::dsl::*;
-    use crate::schema::users;
+    use crate::schema::{decks, memories, users};
+
+    let claimed_ids = conn.transaction::<Vec<i32>, diesel::result::Error, _>(|| {
+        let muted_decks = decks::table
+            .select(decks::id.nullable())
+            .filter(decks::notifications_enabled.eq(false));
+        let muted_memories = memories::table
+            .select(memories::id)
+            .filter(memories::deck_id.eq_any(muted_decks));
+        let silent_users = users::table
+            .select(users::id)
+            .filter(users::verified.eq(false).or(users::unsubscribed.eq(true)));
+        let silent_memories = memories::table
+            .select(memories::id)
+            .filter(memories::user_id.eq_any(silent_users));
+        let is_due = next_run
+            .is_not_null()
+            .and(next_run.le(at_secs))
+            .and(status.eq(models::ScheduleStatus::Active.as_str()))
+            .and(memory_id.ne_all(muted_memories))
+            .and(memory_id.ne_all(silent_memories))
+            .and(locked_until.is_null().or(locked_until.lt(at_secs)));
+        let mut due_ids = schedules
+            .select(id)
+            .filter(is_due)
+            .order(next_run.asc())
+            .limit(limit)
+            .for_update()
+            .skip_locked()
+            .load::<i32>(conn)?;
+
+        // the rest of digests which were cut by the limit, so one digest isn't split between runs
+        let digest_users = schedules
+            .inner_join(memories::table.inner_join(users::table))
+            .select(users::id)
+            .filter(id.eq_any(&due_ids))
+            .filter(users::delivery_mode.ne(delivery::DeliveryMode::Immediate.as_str()))
+            .distinct()
+            .load::<i32>(conn)?;
+        let digest_memories = memories::table
+            .select(memories::id)
+            .filter(memories::user_id.eq_any(digest_users));
+        let rest_ids = schedules
+            .select(id)
+            .filter(is_due)
+            .filter(memory_id.eq_any(digest_memories))
+            .filter(id.ne_all(&due_ids))
+            .for_update()
+            .skip_locked()
+            .load::<i32>(conn)?;
+        due_ids.extend(rest_ids);
+
+        diesel::update(schedules.filter(id.eq_any(&due_ids)))
+            .set((locked_by.eq(owner), locked_until.eq(lease_until)))
+            .execute(conn)?;
+
+        Ok(due_ids)
+    })?;
 
     let curr_schedules = schedules
-        .filter(next_run.is_not_null().and(next_run.le(at_secs)))
-        .inner_join(memories::table.inner_join(users::table))
+        .filter(id.eq_any(claimed_ids))
+        .inner_join(
+            memories::table
+                .inner_join(users::table)
+                .left_join(decks::table),
+        )
         .load::<models::ScheduleWithMemoryAndUser>(conn)?;
 
     Ok(curr_schedules)
 }
 
-pub fn update_schedule_time(
+/// Saves new state of a leased schedule and releases the lease.
+/// Next run is planned again when user changed delivery settings during the delivery.
+/// Returns `None` when lease was lost, e.g. expired and taken by another instance.
+pub fn complete_lease(
+    id_to_update: i32,
+    owner: &str,
+    state: &ScheduleState,
+    conn: &PgConnection,
+) -> Result<Option<models::Schedule>, diesel::result::Error> {
+    use crate::schema::schedules::dsl::*;
+    use crate::schema::{memories, users};
+
+    conn.transaction(|| {
+        let completed =
+            diesel::update(schedules.filter(id.eq(id_to_update).and(locked_by.eq(owner))))
+                .set((
+                    phase_number.eq(state.phase_number),
+                    next_run.eq(state.next_run),
+                    ease_factor.eq(state.ease_factor),
+                    interval_secs.eq(state.interval_secs),
+                    repetitions.eq(state.repetitions),
+                    stability.eq(state.stability),
+                    difficulty.eq(state.difficulty),
+                    locked_by.eq(None::<String>),
+                    locked_until.eq(None::<i64>),
+                    attempts.eq(0),
+                    last_error.eq(None::<String>),
+                ))
+                .get_result::<models::Schedule>(conn)
+                .optional()?;
+        let completed = match completed {
+            None => return Ok(None),
+            Some(c) => c,
+        };
+
+        let user = users::table
+            .inner_join(memories::table)
+            .select(users::all_columns)
+            .filter(memories::id.eq(completed.memory_id))
+            .first::<models::User>(conn)?;
+        let planned = delivery::plan_next_run(ScheduleState::from(&completed), &user);
+        if planned.next_run == completed.next_run {
+            return Ok(Some(completed));
+        }
+
+        update_schedule_state(completed.id, &planned, conn).map(Some)
+    })
+}
+
+/// Records failed delivery of a leased schedule and releases the lease.
+/// Schedule is retried at `retry_at`, or marked as failed without next run when there is no retry.
+pub fn fail_lease(
     id_to_update: i32,
-    new_phase: i32,
-    new_time: Option<i64>,
+    owner: &str,
+    error: &str,
+    retry_at: Option<i64>,
+    conn: &PgConnection,
+) -> Result<Option<models::Schedule>, diesel::result::Error> {
+    use crate::schema::schedules::dsl::*;
+
+    let target = schedules.filter(id.eq(id_to_update).and(locked_by.eq(owner)));
+    let new_status = match retry_at {
+        Some(_) => models::ScheduleStatus::Active,
+        None => models::ScheduleStatus::Failed,
+    };
+
+    diesel::update(target)
+        .set((
+            next_run.eq(retry_at),
+            status.eq(new_status.as_str()),
+            attempts.eq(attempts + 1),
+            last_error.eq(error),
+            locked_by.eq(None::<String>),
+            locked_until.eq(None::<i64>),
+        ))
+        .get_result::<models::Schedule>(conn)
+        .optional()
+}
+
+pub fn get_failed_schedules(
+    conn: &PgConnection,
+) -> Result<Vec<models::ScheduleWithMemoryAndUser>, diesel::result::Error> {
+    use crate::schema::schedules::dsl::*;
+    use crate::schema::{decks, memories, users};
+
+    schedules
+        .filter(status.eq(models::ScheduleStatus::Failed.as_str()))
+        .inner_join(
+            memories::table
+                .inner_join(users::table)
+                .left_join(decks::table),
+        )
+        .order(id.asc())
+        .load::<models::ScheduleWithMemoryAndUser>(conn)
+}
+
+/// Puts failed schedule back to delivery queue, returns `None` when there is no such failed schedule.
+pub fn requeue_schedule(
+    id_to_requeue: i32,
+    at_secs: i64,
+    conn: &PgConnection,
+) -> Result<Option<models::Schedule>, diesel::result::Error> {
+    use crate::schema::schedules::dsl::*;
+
+    diesel::update(
+        schedules.filter(
+            id.eq(id_to_requeue)
+                .and(status.eq(models::ScheduleStatus::Failed.as_str())),
+        ),
+    )
+    .set((
+        status.eq(models::ScheduleStatus::Active.as_str()),
+        next_run.eq(at_secs),
+        attempts.eq(0),
+        last_error.eq(None::<String>),
+    ))
+    .get_result::<models::Schedule>(conn)
+    .optional()
+}
+
+pub fn update_schedule_state(
+    id_to_update: i32,
+    state: &ScheduleState,
     conn: &PgConnection,
 ) -> Result<models::Schedule, diesel::result::Error> {
     use crate::schema::schedules::dsl::*;
 
     diesel::update(schedules.filter(id.eq(id_to_update)))
-        .set((next_run.eq(new_time), phase_number.eq(new_phase)))
+        .set((
+            phase_number.eq(state.phase_number),
+            next_run.eq(state.next_run),
+            ease_factor.eq(state.ease_factor),
+            interval_secs.eq(state.interval_secs),
+            repetitions.eq(state.repetitions),
+            stability.eq(state.stability),
+            difficulty.eq(state.difficulty),
+        ))
         .get_result::<models::Schedule>(conn)
 }
 
@@ -63,45 +361,608 @@ struct TimeError;
 #[fail(display = "some database error. todo: fixme")]
 struct DbError;
 
+#[derive(Debug, Fail)]
+#[fail(
+    display = "user with id '{}' has no deck with id '{}'",
+    user_id, deck_id
+)]
+pub struct UnknownDeck {
+    pub user_id: i32,
+    pub deck_id: i32,
+}
+
+#[derive(Debug, Fail)]
+#[fail(
+    display = "reminder of card with schedule id '{}' is being delivered, try again later",
+    schedule_id
+)]
+pub struct ScheduleLeased {
+    pub schedule_id: i32,
+}
+
+#[derive(Debug, Fail)]
+pub enum RestoreError {
+    #[fail(display = "unknown schedule status '{}'", status)]
+    UnknownStatus { status: String },
+    #[fail(display = "memory has no cards")]
+    NoCards,
+    #[fail(display = "deck '{}' is not among exported decks", name)]
+    UnknownDeck { name: String },
+    #[fail(display = "memory has no card '{}'", card)]
+    UnknownCard { card: i32 },
+    #[fail(display = "card '{}' is exported twice", card)]
+    DuplicateCard { card: i32 },
+    #[fail(
+        display = "phase '{}' of card '{}' isn't in the memory's phase set",
+        phase_number, card
+    )]
+    UnknownPhase { card: i32, phase_number: i32 },
+    #[fail(display = "unknown delivery status '{}'", status)]
+    UnknownDeliveryStatus { status: String },
+    #[fail(display = "unknown grade '{}'", grade)]
+    UnknownGrade { grade: String },
+    #[fail(display = "unknown channel '{}'", channel)]
+    UnknownChannel { channel: String },
+}
+
+/// Fails with `UnknownDeck` unless deck with `checked_deck_id` belongs to user with `owner_id`.
+fn check_deck(
+    checked_deck_id: Option<i32>,
+    owner_id: i32,
+    conn: &PgConnection,
+) -> Result<(), failure::Error> {
+    use crate::schema::decks::dsl::*;
+
+    let checked_deck_id = match checked_deck_id {
+        None => return Ok(()),
+        Some(d) => d,
+    };
+    let owned = diesel::select(diesel::dsl::exists(
+        decks.filter(id.eq(checked_deck_id).and(user_id.eq(owner_id))),
+    ))
+    .get_result::<bool>(conn)?;
+    if !owned {
+        return Err(UnknownDeck {
+            user_id: owner_id,
+            deck_id: checked_deck_id,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Creates memory with a schedule for each of its cards.
 pub fn insert_reminder(
-    new_user_id: i32,
-    new_topic: Option<&str>,
-    new_text: &str,
+    new_memory: &models::NewMemory,
+    tag_names: &[String],
+    phase_sets: &PhaseSets,
+    conn: &PgConnection,
+) -> Result<i32, failure::Error> {
+    conn.transaction::<i32, failure::Error, _>(|| {
+        check_deck(new_memory.deck_id, new_memory.user_id, conn)?;
+        let owner = get_user(new_memory.user_id, conn)?.ok_or(diesel::result::Error::NotFound)?;
+        let deck = match new_memory.deck_id {
+            Some(new_deck_id) => get_deck(new_deck_id, conn)?,
+            None => None,
+        };
+
+        create_memory(
+            new_memory,
+            tag_names,
+            &owner,
+            deck.as_ref(),
+            phase_sets,
+            conn,
+        )
+    })
+}
+
+/// Inserts memories in one transaction the same way `insert_reminder` does, all of them go into `in_deck`.
+/// Memory that fails is rolled back alone and its error takes its place in the result, the others are kept.
+pub fn import_memories(
+    owner_id: i32,
+    in_deck: Option<i32>,
+    new_memories: &[(models::NewMemory, &[String])],
+    phase_sets: &PhaseSets,
+    conn: &PgConnection,
+) -> Result<Vec<Result<i32, failure::Error>>, failure::Error> {
+    conn.transaction::<Vec<Result<i32, failure::Error>>, failure::Error, _>(|| {
+        check_deck(in_deck, owner_id, conn)?;
+        let owner = get_user(owner_id, conn)?.ok_or(diesel::result::Error::NotFound)?;
+        let deck = match in_deck {
+            Some(in_deck_id) => get_deck(in_deck_id, conn)?,
+            None => None,
+        };
+
+        // transaction of every memory is nested, so it is a savepoint
+        Ok(new_memories
+            .iter()
+            .map(|(new_memory, tag_names)| {
+                conn.transaction(|| {
+                    create_memory(
+                        new_memory,
+                        tag_names,
+                        &owner,
+                        deck.as_ref(),
+                        phase_sets,
+                        conn,
+                    )
+                })
+            })
+            .collect())
+    })
+}
+
+/// Inserts memory of `owner` in `deck` with its tags and cards, must be called in a transaction.
+fn create_memory(
+    new_memory: &models::NewMemory,
+    tag_names: &[String],
+    owner: &models::User,
+    deck: Option<&models::Deck>,
+    phase_sets: &PhaseSets,
     conn: &PgConnection,
 ) -> Result<i32, failure::Error> {
     use crate::schema::memories::dsl::*;
-    use crate::schema::schedules::dsl::*;
 
-    let result = conn.transaction::<models::Memory, failure::Error, _>(move || {
+    let created_memory = diesel::insert_into(memories)
+        .values(new_memory)
+        .get_result::<models::Memory>(conn)?;
+    set_memory_tags(created_memory.id, created_memory.user_id, tag_names, conn)?;
+
+    let next_run_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_err(|_| TimeError)?;
+    let memory_with_user = models::MemoryWithUser {
+        memory: created_memory,
+        user: owner.clone(),
+        deck: deck.cloned(),
+    };
+    let new_cards: Vec<i32> = cards::card_numbers(&memory_with_user.memory)
+        .into_iter()
+        .collect();
+    insert_cards(
+        &memory_with_user,
+        &new_cards,
+        phase_sets,
+        next_run_time.as_secs() as i64,
+        conn,
+    )?;
+
+    Ok(memory_with_user.memory.id)
+}
+
+/// Memories of user with all their cards, tags, decks and reviews, `None` when there is no such user.
+pub fn get_user_export(
+    owner_id: i32,
+    conn: &PgConnection,
+) -> Result<Option<Export>, diesel::result::Error> {
+    use crate::schema::{decks, memories, reviews, schedules};
+
+    if get_user(owner_id, conn)?.is_none() {
+        return Ok(None);
+    }
+
+    let user_decks = decks::table
+        .filter(decks::user_id.eq(owner_id))
+        .order(decks::name.asc())
+        .load::<models::Deck>(conn)?;
+    let user_memories = memories::table
+        .filter(memories::user_id.eq(owner_id))
+        .order(memories::id.asc())
+        .load::<models::Memory>(conn)?;
+    let owned_memories = || {
+        memories::table
+            .select(memories::id)
+            .filter(memories::user_id.eq(owner_id))
+    };
+    let memory_schedules = schedules::table
+        .filter(schedules::memory_id.eq_any(owned_memories()))
+        .order((schedules::memory_id.asc(), schedules::card.asc()))
+        .load::<models::Schedule>(conn)?;
+    let history = reviews::table
+        .inner_join(schedules::table)
+        .filter(schedules::memory_id.eq_any(owned_memories()))
+        .order((reviews::sent_at.asc(), reviews::id.asc()))
+        .select(reviews::all_columns)
+        .load::<models::Review>(conn)?;
+    let memory_ids: Vec<i32> = user_memories.iter().map(|m| m.id).collect();
+    let tags = get_memory_tags(&memory_ids, conn)?;
+
+    Ok(Some(Export::new(
+        user_decks,
+        user_memories,
+        memory_schedules,
+        history,
+        tags,
+    )))
+}
+
+/// Restores exported memories of user in one transaction with their tags, schedules and history as they were.
+/// Exported decks are created, user's decks with the same names are used as they are.
+/// Memory that fails is rolled back alone and its error takes its place in the result, the others are kept.
+pub fn restore_memories(
+    owner_id: i32,
+    export: &Export,
+    phase_sets: &PhaseSets,
+    conn: &PgConnection,
+) -> Result<Vec<Result<i32, failure::Error>>, failure::Error> {
+    use crate::schema::{decks, users};
+
+    conn.transaction::<Vec<Result<i32, failure::Error>>, failure::Error, _>(|| {
+        let new_decks: Vec<models::NewDeck> = export
+            .decks
+            .iter()
+            .map(|deck| models::NewDeck {
+                user_id: owner_id,
+                name: &deck.name,
+                phase_set: deck.phase_set.as_deref(),
+                notifications_enabled: deck.notifications_enabled,
+            })
+            .collect();
+        diesel::insert_into(decks::table)
+            .values(&new_decks)
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+        let user_decks: BTreeMap<String, (i32, Option<String>)> = decks::table
+            .filter(decks::user_id.eq(owner_id))
+            .select((decks::name, decks::id, decks::phase_set))
+            .load::<(String, i32, Option<String>)>(conn)?
+            .into_iter()
+            .filter(|(name, _, _)| export.decks.iter().any(|d| &d.name == name))
+            .map(|(name, deck_id, deck_set)| (name, (deck_id, deck_set)))
+            .collect();
+        let user_set = users::table
+            .find(owner_id)
+            .select(users::phase_set)
+            .first::<Option<String>>(conn)?;
+
+        Ok(export
+            .memories
+            .iter()
+            .map(|memory| {
+                restore_memory(
+                    owner_id,
+                    memory,
+                    &user_decks,
+                    user_set.as_deref(),
+                    phase_sets,
+                    conn,
+                )
+            })
+            .collect())
+    })
+}
+
+/// Fails with `RestoreError` when any card, its phase or history can't belong to memory.
+fn check_restored_cards(exported: &ExportedMemory, phases: &Phases) -> Result<(), RestoreError> {
+    if exported.cards.is_empty() {
+        return Err(RestoreError::NoCards);
+    }
+
+    let statuses = [
+        models::ScheduleStatus::Active,
+        models::ScheduleStatus::Paused,
+        models::ScheduleStatus::Failed,
+        models::ScheduleStatus::Retired,
+    ];
+    let delivery_statuses = [
+        models::DeliveryStatus::Sent,
+        models::DeliveryStatus::Failed,
+        models::DeliveryStatus::NotSent,
+    ];
+    let grades = [Grade::Again, Grade::Hard, Grade::Good, Grade::Easy];
+    let wanted = cards::text_card_numbers(&exported.text);
+    let mut seen = BTreeSet::new();
+    for card in exported.cards.iter() {
+        if statuses.iter().all(|s| s.as_str() != card.status) {
+            return Err(RestoreError::UnknownStatus {
+                status: card.status.clone(),
+            });
+        }
+        // cards of removed clozes are kept retired with their history
+        let retired = card.status == models::ScheduleStatus::Retired.as_str();
+        if !retired && !wanted.contains(&card.card) {
+            return Err(RestoreError::UnknownCard { card: card.card });
+        }
+        if !seen.insert(card.card) {
+            return Err(RestoreError::DuplicateCard { card: card.card });
+        }
+        if phases.get(card.phase_number).is_none() {
+            return Err(RestoreError::UnknownPhase {
+                card: card.card,
+                phase_number: card.phase_number,
+            });
+        }
+
+        for review in card.history.iter() {
+            if delivery_statuses
+                .iter()
+                .all(|s| s.as_str() != review.delivery_status)
+            {
+                return Err(RestoreError::UnknownDeliveryStatus {
+                    status: review.delivery_status.clone(),
+                });
+            }
+            if let Some(grade) = &review.grade {
+                if grades.iter().all(|g| g.as_str() != grade) {
+                    return Err(RestoreError::UnknownGrade {
+                        grade: grade.clone(),
+                    });
+                }
+            }
+            if !models::REVIEW_CHANNELS.contains(&review.channel.as_str()) {
+                return Err(RestoreError::UnknownChannel {
+                    channel: review.channel.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn restore_memory(
+    owner_id: i32,
+    exported: &ExportedMemory,
+    user_decks: &BTreeMap<String, (i32, Option<String>)>,
+    user_set: Option<&str>,
+    phase_sets: &PhaseSets,
+    conn: &PgConnection,
+) -> Result<i32, failure::Error> {
+    use crate::schema::{memories, reviews, schedules};
+
+    exported.format.parse::<TextFormat>()?;
+    if let Some(algorithm) = &exported.algorithm {
+        algorithm.parse::<AlgorithmKind>()?;
+    }
+    let deck = match &exported.deck {
+        None => None,
+        Some(name) => match user_decks.get(name) {
+            None => return Err(RestoreError::UnknownDeck { name: name.clone() }.into()),
+            Some(deck) => Some(deck),
+        },
+    };
+    let set_name = deck
+        .and_then(|(_, deck_set)| deck_set.as_deref())
+        .or(user_set)
+        .unwrap_or(DEFAULT_PHASE_SET);
+    check_restored_cards(exported, phase_sets.get_or_default(set_name))?;
+
+    conn.transaction::<i32, failure::Error, _>(|| {
         let new_memory = models::NewMemory {
-            user_id: new_user_id,
-            topic: new_topic,
-            text: new_text,
+            user_id: owner_id,
+            topic: exported.topic.as_deref(),
+            text: &exported.text,
+            algorithm: exported.algorithm.as_deref(),
+            deck_id: deck.map(|(deck_id, _)| *deck_id),
+            format: &exported.format,
+            back: exported.back.as_deref(),
         };
-        let created_memory = diesel::insert_into(memories)
+        let restored_id = diesel::insert_into(memories::table)
             .values(&new_memory)
-            .get_result::<models::Memory>(conn)
-            .map_err(|_| DbError)?;
+            .returning(memories::id)
+            .get_result::<i32>(conn)?;
+        set_memory_tags(restored_id, owner_id, &exported.tags, conn)?;
 
-        let next_run_time = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .map_err(|_| TimeError)?;
+        for card in exported.cards.iter() {
+            let schedule_id = diesel::insert_into(schedules::table)
+                .values(&models::RestoredSchedule {
+                    memory_id: restored_id,
+                    card: card.card,
+                    phase_number: card.phase_number,
+                    next_run: card.next_run,
+                    ease_factor: card.ease_factor,
+                    interval_secs: card.interval_secs,
+                    repetitions: card.repetitions,
+                    stability: card.stability,
+                    difficulty: card.difficulty,
+                    status: &card.status,
+                    attempts: card.attempts,
+                    last_error: card.last_error.as_deref(),
+                })
+                .returning(schedules::id)
+                .get_result::<i32>(conn)?;
+            let history: Vec<models::NewReview> = card
+                .history
+                .iter()
+                .map(|review| models::NewReview {
+                    schedule_id,
+                    phase_number: review.phase_number,
+                    sent_at: review.sent_at,
+                    channel: &review.channel,
+                    delivery_status: &review.delivery_status,
+                    grade: review.grade.as_deref(),
+                    response_latency: review.response_latency,
+                })
+                .collect();
+            diesel::insert_into(reviews::table)
+                .values(&history)
+                .execute(conn)?;
+        }
 
-        let new_schedule = models::NewSchedule {
-            memory_id: created_memory.id,
-            phase_number: 1,
-            next_run: Some(next_run_time.as_secs() as i64),
-        };
+        Ok(restored_id)
+    })
+}
 
-        diesel::insert_into(schedules)
-            .values(new_schedule)
-            .execute(conn)
-            .map_err(|_| DbError)?;
+/// Starts schedules of `new_cards` of memory from the first phase.
+fn insert_cards(
+    memory_with_user: &models::MemoryWithUser,
+    new_cards: &[i32],
+    phase_sets: &PhaseSets,
+    now: i64,
+    conn: &PgConnection,
+) -> Result<(), diesel::result::Error> {
+    use crate::schema::schedules::dsl::*;
 
-        Ok(created_memory)
-    })?;
+    let phases = phase_sets.for_memory(memory_with_user);
+    let first_state = delivery::plan_next_run(
+        ScheduleState::new(phases.first_number(), now),
+        &memory_with_user.user,
+    );
+    let new_schedules: Vec<models::NewSchedule> = new_cards
+        .iter()
+        .map(|card_number| models::NewSchedule {
+            memory_id: memory_with_user.memory.id,
+            phase_number: first_state.phase_number,
+            next_run: first_state.next_run,
+            card: *card_number,
+        })
+        .collect();
+
+    diesel::insert_into(schedules)
+        .values(&new_schedules)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Changes user, pending reminders are moved to slots of new delivery mode, timezone or quiet hours.
+pub fn update_user(
+    user_id: i32,
+    changes: &models::UserChanges,
+    conn: &PgConnection,
+) -> Result<Option<models::User>, diesel::result::Error> {
+    use crate::schema::users::dsl::*;
+
+    let replans = changes.timezone.is_some()
+        || changes.quiet_start.is_some()
+        || changes.quiet_end.is_some()
+        || changes.delivery_mode.is_some()
+        || changes.digest_time.is_some();
+
+    conn.transaction(|| {
+        if replans || changes.phase_set.is_some() || changes.unsubscribed.is_some() {
+            diesel::update(users.filter(id.eq(user_id)))
+                .set(changes)
+                .execute(conn)?;
+        }
+
+        let user = get_user(user_id, conn)?;
+        if let (true, Some(user)) = (replans, &user) {
+            replan_schedules(user, conn)?;
+        }
 
-    Ok(result.id)
+        Ok(user)
+    })
+}
+
+/// Plans pending reminders of user again from their current next run, reminders which are being delivered
+/// are planned when their delivery completes.
+fn replan_schedules(user: &models::User, conn: &PgConnection) -> Result<(), diesel::result::Error> {
+    use crate::schema::memories;
+    use crate::schema::schedules::dsl::*;
+
+    let user_memories = memories::table
+        .select(memories::id)
+        .filter(memories::user_id.eq(user.id));
+    let pending = schedules
+        .filter(memory_id.eq_any(user_memories))
+        .filter(status.eq(models::ScheduleStatus::Active.as_str()))
+        .filter(next_run.is_not_null())
+        .filter(locked_by.is_null())
+        .for_update()
+        .load::<models::Schedule>(conn)?;
+
+    for schedule in pending.iter() {
+        let state = delivery::plan_next_run(ScheduleState::from(schedule), user);
+        if state.next_run != schedule.next_run {
+            update_schedule_state(schedule.id, &state, conn)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces verification token of user, so only the latest mailed link works.
+pub fn replace_verification_token(
+    new_token: &models::NewVerificationToken,
+    conn: &PgConnection,
+) -> Result<(), diesel::result::Error> {
+    use crate::schema::verification_tokens::dsl::*;
+
+    conn.transaction(|| {
+        diesel::delete(verification_tokens.filter(user_id.eq(new_token.user_id))).execute(conn)?;
+        diesel::insert_into(verification_tokens)
+            .values(new_token)
+            .execute(conn)?;
+
+        Ok(())
+    })
+}
+
+/// Marks user with the token as verified, the token can be used only once.
+/// Returns `None` when there is no such token or it was issued before `issued_since`.
+pub fn verify_user(
+    hash: &str,
+    issued_since: i64,
+    conn: &PgConnection,
+) -> Result<Option<models::User>, diesel::result::Error> {
+    use crate::schema::{users, verification_tokens};
+
+    conn.transaction(|| {
+        let owner = diesel::delete(
+            verification_tokens::table
+                .filter(verification_tokens::token_hash.eq(hash))
+                .filter(verification_tokens::created_at.ge(issued_since)),
+        )
+        .returning(verification_tokens::user_id)
+        .get_result::<i32>(conn)
+        .optional()?;
+
+        match owner {
+            None => Ok(None),
+            Some(owner) => diesel::update(users::table.filter(users::id.eq(owner)))
+                .set(users::verified.eq(true))
+                .get_result::<models::User>(conn)
+                .optional(),
+        }
+    })
+}
+
+/// Stops all reminders of user, returns `false` when there is no such user.
+pub fn unsubscribe_user(user_id: i32, conn: &PgConnection) -> Result<bool, diesel::result::Error> {
+    use crate::schema::users::dsl::*;
+
+    let updated = diesel::update(users.filter(id.eq(user_id)))
+        .set(unsubscribed.eq(true))
+        .execute(conn)?;
+
+    Ok(updated > 0)
+}
+
+pub fn get_user_by_key_hash(
+    hash: &str,
+    conn: &PgConnection,
+) -> Result<Option<models::User>, diesel::result::Error> {
+    use crate::schema::{api_keys, users};
+
+    api_keys::table
+        .inner_join(users::table)
+        .filter(api_keys::key_hash.eq(hash))
+        .select(users::all_columns)
+        .first::<models::User>(conn)
+        .optional()
+}
+
+/// Replaces API keys of user with the new one, returns `false` when there is no such user.
+pub fn replace_api_key(
+    new_key: &models::NewApiKey,
+    conn: &PgConnection,
+) -> Result<bool, diesel::result::Error> {
+    use crate::schema::api_keys::dsl::*;
+
+    conn.transaction(|| {
+        if get_user(new_key.user_id, conn)?.is_none() {
+            return Ok(false);
+        }
+        diesel::delete(api_keys.filter(user_id.eq(new_key.user_id))).execute(conn)?;
+        diesel::insert_into(api_keys)
+            .values(new_key)
+            .execute(conn)?;
+
+        Ok(true)
+    })
 }
 
 pub fn get_user(
@@ -117,3 +978,1172 @@ pub fn get_user(
 
     Ok(result)
 }
+
+/// Locks schedules of memory cards which are not retired, ordered by card.
+fn lock_memory_schedules(
+    locked_memory_id: i32,
+    conn: &PgConnection,
+) -> Result<Vec<models::ScheduleWithMemoryAndUser>, diesel::result::Error> {
+    use crate::schema::schedules::dsl::*;
+    use crate::schema::{decks, memories, users};
+
+    // postgres can't lock rows of a left join, so schedules are locked alone
+    let locked_ids = schedules
+        .select(id)
+        .filter(memory_id.eq(locked_memory_id))
+        .filter(status.ne(models::ScheduleStatus::Retired.as_str()))
+        .for_update()
+        .load::<i32>(conn)?;
+
+    schedules
+        .filter(id.eq_any(locked_ids))
+        .inner_join(
+            memories::table
+                .inner_join(users::table)
+                .left_join(decks::table),
+        )
+        .order(card.asc())
+        .load::<models::ScheduleWithMemoryAndUser>(conn)
+}
+
+/// Locks card with schedule `locked_schedule_id` unless it is retired.
+fn lock_schedule(
+    locked_schedule_id: i32,
+    conn: &PgConnection,
+) -> Result<Option<models::ScheduleWithMemoryAndUser>, diesel::result::Error> {
+    use crate::schema::schedules::dsl::*;
+    use crate::schema::{decks, memories, users};
+
+    let locked_id = schedules
+        .select(id)
+        .filter(id.eq(locked_schedule_id))
+        .filter(status.ne(models::ScheduleStatus::Retired.as_str()))
+        .for_update()
+        .first::<i32>(conn)
+        .optional()?;
+
+    match locked_id {
+        None => Ok(None),
+        Some(locked_id) => schedules
+            .filter(id.eq(locked_id))
+            .inner_join(
+                memories::table
+                    .inner_join(users::table)
+                    .left_join(decks::table),
+            )
+            .first::<models::ScheduleWithMemoryAndUser>(conn)
+            .optional(),
+    }
+}
+
+/// Card of memory that was reminded last, the first one when none was reminded yet.
+fn last_reminded_card(
+    memory_cards: Vec<models::ScheduleWithMemoryAndUser>,
+    conn: &PgConnection,
+) -> Result<Option<models::ScheduleWithMemoryAndUser>, diesel::result::Error> {
+    use crate::schema::reviews::dsl::*;
+
+    let card_ids: Vec<i32> = memory_cards.iter().map(|c| c.schedule.id).collect();
+    let last_reminded = reviews
+        .filter(schedule_id.eq_any(card_ids))
+        .filter(delivery_status.eq(models::DeliveryStatus::Sent.as_str()))
+        .order((sent_at.desc(), id.desc()))
+        .select(schedule_id)
+        .first::<i32>(conn)
+        .optional()?;
+    let position = last_reminded
+        .and_then(|reminded_id| {
+            memory_cards
+                .iter()
+                .position(|c| c.schedule.id == reminded_id)
+        })
+        .unwrap_or(0);
+
+    Ok(memory_cards.into_iter().nth(position))
+}
+
+/// Grades `reviewed_card` of memory, or the card that was reminded last when it is not set.
+/// Returns `None` when there is no such memory or card.
+pub fn review_memory(
+    reviewed_memory_id: i32,
+    reviewed_card: Option<i32>,
+    grade: Grade,
+    phase_sets: &PhaseSets,
+    conn: &PgConnection,
+) -> Result<Option<models::Schedule>, failure::Error> {
+    conn.transaction::<Option<models::Schedule>, failure::Error, _>(|| {
+        let memory_cards = lock_memory_schedules(reviewed_memory_id, conn)?;
+        let reviewed = match reviewed_card {
+            Some(number) => memory_cards.into_iter().find(|c| c.schedule.card == number),
+            None => last_reminded_card(memory_cards, conn)?,
+        };
+
+        match reviewed {
+            None => Ok(None),
+            Some(r) => review_locked(r, grade, phase_sets, conn).map(Some),
+        }
+    })
+}
+
+/// Grades card with schedule `reviewed_schedule_id`, returns `None` when there is no such card.
+pub fn review_card(
+    reviewed_schedule_id: i32,
+    grade: Grade,
+    phase_sets: &PhaseSets,
+    conn: &PgConnection,
+) -> Result<Option<models::Schedule>, failure::Error> {
+    conn.transaction::<Option<models::Schedule>, failure::Error, _>(|| {
+        match lock_schedule(reviewed_schedule_id, conn)? {
+            None => Ok(None),
+            Some(r) => review_locked(r, grade, phase_sets, conn).map(Some),
+        }
+    })
+}
+
+/// Grades card which row is locked, fails with `ScheduleLeased` while the scheduler delivers its reminder,
+/// otherwise the scheduler would overwrite the new state when it completes the lease.
+fn review_locked(
+    reviewed: models::ScheduleWithMemoryAndUser,
+    grade: Grade,
+    phase_sets: &PhaseSets,
+    conn: &PgConnection,
+) -> Result<models::Schedule, failure::Error> {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_err(|_| TimeError)?
+        .as_secs() as i64;
+    if reviewed
+        .schedule
+        .locked_until
+        .is_some_and(|until| until >= now)
+    {
+        return Err(ScheduleLeased {
+            schedule_id: reviewed.schedule.id,
+        }
+        .into());
+    }
+    let kind = AlgorithmKind::resolve(
+        &reviewed.memory_with_user.memory,
+        &reviewed.memory_with_user.user,
+    )?;
+    let phases = phase_sets.for_memory(&reviewed.memory_with_user);
+    let new_state = delivery::plan_next_run(
+        scheduling::algorithm(kind, phases).after_review(
+            &ScheduleState::from(&reviewed.schedule),
+            grade,
+            now,
+        ),
+        &reviewed.memory_with_user.user,
+    );
+    let updated = update_schedule_state(reviewed.schedule.id, &new_state, conn)?;
+    record_grade(
+        reviewed.schedule.id,
+        reviewed.schedule.phase_number,
+        grade,
+        now,
+        conn,
+    )?;
+
+    Ok(updated)
+}
+
+pub fn insert_review(
+    new_review: &models::NewReview,
+    conn: &PgConnection,
+) -> Result<models::Review, diesel::result::Error> {
+    use crate::schema::reviews::dsl::*;
+
+    diesel::insert_into(reviews)
+        .values(new_review)
+        .get_result::<models::Review>(conn)
+}
+
+/// Puts grade on the last delivered and not yet graded reminder of schedule,
+/// or records a separate review when there is no such reminder.
+fn record_grade(
+    graded_schedule_id: i32,
+    graded_phase_number: i32,
+    new_grade: Grade,
+    graded_at: i64,
+    conn: &PgConnection,
+) -> Result<models::Review, diesel::result::Error> {
+    use crate::schema::reviews::dsl::*;
+
+    let last_delivered = reviews
+        .filter(schedule_id.eq(graded_schedule_id))
+        .filter(delivery_status.eq(models::DeliveryStatus::Sent.as_str()))
+        .filter(grade.is_null())
+        .order(sent_at.desc())
+        .first::<models::Review>(conn)
+        .optional()?;
+
+    match last_delivered {
+        Some(review) => diesel::update(reviews.filter(id.eq(review.id)))
+            .set((
+                grade.eq(new_grade.as_str()),
+                response_latency.eq(graded_at - review.sent_at),
+            ))
+            .get_result::<models::Review>(conn),
+        None => insert_review(
+            &models::NewReview {
+                schedule_id: graded_schedule_id,
+                phase_number: graded_phase_number,
+                sent_at: graded_at,
+                channel: "api",
+                delivery_status: models::DeliveryStatus::NotSent.as_str(),
+                grade: Some(new_grade.as_str()),
+                response_latency: None,
+            },
+            conn,
+        ),
+    }
+}
+
+pub fn get_history(
+    history_memory_id: i32,
+    conn: &PgConnection,
+) -> Result<Option<Vec<models::Review>>, diesel::result::Error> {
+    use crate::schema::reviews::dsl::*;
+    use crate::schema::{memories, schedules};
+
+    let memory_exists = diesel::select(diesel::dsl::exists(
+        memories::table.filter(memories::id.eq(history_memory_id)),
+    ))
+    .get_result::<bool>(conn)?;
+    if !memory_exists {
+        return Ok(None);
+    }
+
+    let history = reviews
+        .inner_join(schedules::table)
+        .filter(schedules::memory_id.eq(history_memory_id))
+        .order((sent_at.asc(), id.asc()))
+        .select(crate::schema::reviews::all_columns)
+        .load::<models::Review>(conn)?;
+
+    Ok(Some(history))
+}
+
+/// Page of user's memories, only from `in_deck` and with `tagged` tag when they are set,
+/// with total count of them. Returns `None` when there is no such user.
+pub fn get_user_memories(
+    owner_id: i32,
+    in_deck: Option<i32>,
+    tagged: Option<&str>,
+    offset: i64,
+    limit: i64,
+    conn: &PgConnection,
+) -> Result<Option<(Vec<models::MemoryWithSchedules>, i64)>, diesel::result::Error> {
+    use crate::schema::memories::dsl::*;
+    use crate::schema::{memory_tags, tags};
+
+    if get_user(owner_id, conn)?.is_none() {
+        return Ok(None);
+    }
+
+    let mut count_query = memories.filter(user_id.eq(owner_id)).into_boxed();
+    let mut page_query = memories.filter(user_id.eq(owner_id)).into_boxed();
+    if let Some(in_deck) = in_deck {
+        count_query = count_query.filter(deck_id.eq(in_deck));
+        page_query = page_query.filter(deck_id.eq(in_deck));
+    }
+    if let Some(tagged) = tagged {
+        let tagged_memories = || {
+            memory_tags::table
+                .inner_join(tags::table)
+                .filter(tags::user_id.eq(owner_id).and(tags::name.eq(tagged)))
+                .select(memory_tags::memory_id)
+        };
+        count_query = count_query.filter(id.eq_any(tagged_memories()));
+        page_query = page_query.filter(id.eq_any(tagged_memories()));
+    }
+
+    let total = count_query.count().get_result::<i64>(conn)?;
+    let page = page_query
+        .order(id.asc())
+        .offset(offset)
+        .limit(limit)
+        .load::<models::Memory>(conn)?;
+
+    Ok(Some((with_schedules(page, conn)?, total)))
+}
+
+/// Puts schedules of cards which are not retired next to each memory.
+fn with_schedules(
+    loaded: Vec<models::Memory>,
+    conn: &PgConnection,
+) -> Result<Vec<models::MemoryWithSchedules>, diesel::result::Error> {
+    use crate::schema::schedules::dsl::*;
+
+    let loaded_ids: Vec<i32> = loaded.iter().map(|m| m.id).collect();
+    let card_schedules = schedules
+        .filter(memory_id.eq_any(loaded_ids))
+        .filter(status.ne(models::ScheduleStatus::Retired.as_str()))
+        .order((memory_id.asc(), card.asc()))
+        .load::<models::Schedule>(conn)?;
+
+    let mut by_memory: BTreeMap<i32, Vec<models::Schedule>> = BTreeMap::new();
+    for schedule in card_schedules {
+        by_memory
+            .entry(schedule.memory_id)
+            .or_default()
+            .push(schedule);
+    }
+
+    Ok(loaded
+        .into_iter()
+        .map(|memory| models::MemoryWithSchedules {
+            schedules: by_memory.remove(&memory.id).unwrap_or_default(),
+            memory,
+        })
+        .collect())
+}
+
+/// Id of user who owns memory, `None` when there is no such memory.
+pub fn get_memory_owner(
+    memory_id: i32,
+    conn: &PgConnection,
+) -> Result<Option<i32>, diesel::result::Error> {
+    use crate::schema::memories::dsl::*;
+
+    memories
+        .filter(id.eq(memory_id))
+        .select(user_id)
+        .first::<i32>(conn)
+        .optional()
+}
+
+pub fn get_memory(
+    memory_id: i32,
+    conn: &PgConnection,
+) -> Result<Option<models::MemoryWithSchedules>, diesel::result::Error> {
+    use crate::schema::memories::dsl::*;
+
+    let memory = memories
+        .filter(id.eq(memory_id))
+        .first::<models::Memory>(conn)
+        .optional()?;
+
+    match memory {
+        None => Ok(None),
+        Some(m) => Ok(with_schedules(vec![m], conn)?.pop()),
+    }
+}
+
+/// Card with schedule `card_schedule_id` and its memory, `None` when there is no such card or it is retired.
+pub fn get_card(
+    card_schedule_id: i32,
+    conn: &PgConnection,
+) -> Result<Option<(models::Schedule, models::Memory)>, diesel::result::Error> {
+    use crate::schema::memories;
+    use crate::schema::schedules::dsl::*;
+
+    schedules
+        .filter(id.eq(card_schedule_id))
+        .filter(status.ne(models::ScheduleStatus::Retired.as_str()))
+        .inner_join(memories::table)
+        .first::<(models::Schedule, models::Memory)>(conn)
+        .optional()
+}
+
+/// Changes memory, `tag_names` replace all its tags when they are set.
+/// New text reconciles cards of memory with its clozes.
+pub fn update_memory(
+    memory_id: i32,
+    changes: &models::MemoryChanges,
+    tag_names: Option<&[String]>,
+    phase_sets: &PhaseSets,
+    conn: &PgConnection,
+) -> Result<Option<models::MemoryWithSchedules>, failure::Error> {
+    use crate::schema::memories::dsl::*;
+
+    conn.transaction::<Option<models::MemoryWithSchedules>, failure::Error, _>(|| {
+        let memory = match get_memory(memory_id, conn)? {
+            None => return Ok(None),
+            Some(m) => m,
+        };
+        if let Some(new_deck_id) = changes.deck_id {
+            check_deck(new_deck_id, memory.memory.user_id, conn)?;
+        }
+
+        if changes.topic.is_some()
+            || changes.text.is_some()
+            || changes.deck_id.is_some()
+            || changes.format.is_some()
+            || changes.back.is_some()
+        {
+            diesel::update(memories.filter(id.eq(memory_id)))
+                .set(changes)
+                .execute(conn)?;
+        }
+        if changes.text.is_some() {
+            reconcile_cards(memory_id, phase_sets, conn)?;
+        }
+        if let Some(tag_names) = tag_names {
+            set_memory_tags(memory_id, memory.memory.user_id, tag_names, conn)?;
+        }
+
+        Ok(get_memory(memory_id, conn)?)
+    })
+}
+
+/// Brings cards of memory in line with clozes of its text. Cards of new cloze indices start from the first phase,
+/// cards of removed ones are retired, retired cards which cloze is back continue from where they stopped.
+/// Cards of unchanged clozes are left as they are.
+fn reconcile_cards(
+    reconciled_memory_id: i32,
+    phase_sets: &PhaseSets,
+    conn: &PgConnection,
+) -> Result<(), failure::Error> {
+    use crate::schema::schedules::dsl::*;
+    use crate::schema::{decks, memories, users};
+
+    let memory_with_user = memories::table
+        .filter(memories::id.eq(reconciled_memory_id))
+        .inner_join(users::table)
+        .left_join(decks::table)
+        .first::<models::MemoryWithUser>(conn)?;
+    let existing = schedules
+        .filter(memory_id.eq(reconciled_memory_id))
+        .for_update()
+        .load::<models::Schedule>(conn)?;
+    let wanted = cards::card_numbers(&memory_with_user.memory);
+
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_err(|_| TimeError)?
+        .as_secs() as i64;
+    let kind = AlgorithmKind::resolve(&memory_with_user.memory, &memory_with_user.user)?;
+    let phases = phase_sets.for_memory(&memory_with_user);
+    for schedule in existing.iter() {
+        let retired = schedule.status == models::ScheduleStatus::Retired.as_str();
+        match (wanted.contains(&schedule.card), retired) {
+            (false, false) => {
+                // lease is released, so delivery in progress doesn't bring the card back
+                diesel::update(schedules.filter(id.eq(schedule.id)))
+                    .set((
+                        status.eq(models::ScheduleStatus::Retired.as_str()),
+                        locked_by.eq(None::<String>),
+                        locked_until.eq(None::<i64>),
+                    ))
+                    .execute(conn)?;
+            }
+            (true, true) => {
+                let resumed = scheduling::algorithm(kind, phases)
+                    .after_resume(&ScheduleState::from(schedule), now);
+                let new_state = delivery::plan_next_run(resumed, &memory_with_user.user);
+                update_schedule_state(schedule.id, &new_state, conn)?;
+                set_schedule_status(schedule.id, models::ScheduleStatus::Active, conn)?;
+            }
+            _ => {}
+        }
+    }
+
+    let new_cards: Vec<i32> = wanted
+        .into_iter()
+        .filter(|number| existing.iter().all(|s| s.card != *number))
+        .collect();
+    insert_cards(&memory_with_user, &new_cards, phase_sets, now, conn)?;
+
+    Ok(())
+}
+
+/// Sets status of schedule, failed delivery attempts are forgotten.
+fn set_schedule_status(
+    schedule_id: i32,
+    new_status: models::ScheduleStatus,
+    conn: &PgConnection,
+) -> Result<models::Schedule, diesel::result::Error> {
+    use crate::schema::schedules::dsl::*;
+
+    diesel::update(schedules.filter(id.eq(schedule_id)))
+        .set((
+            status.eq(new_status.as_str()),
+            attempts.eq(0),
+            last_error.eq(None::<String>),
+        ))
+        .get_result::<models::Schedule>(conn)
+}
+
+/// Replaces tags of memory with `tag_names`, creates tags the owner doesn't have yet.
+fn set_memory_tags(
+    tagged_memory_id: i32,
+    owner_id: i32,
+    tag_names: &[String],
+    conn: &PgConnection,
+) -> Result<(), diesel::result::Error> {
+    use crate::schema::{memory_tags, tags};
+
+    diesel::delete(memory_tags::table.filter(memory_tags::memory_id.eq(tagged_memory_id)))
+        .execute(conn)?;
+
+    let tag_names: BTreeSet<&str> = tag_names
+        .iter()
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .collect();
+    if tag_names.is_empty() {
+        return Ok(());
+    }
+
+    let new_tags: Vec<models::NewTag> = tag_names
+        .iter()
+        .map(|name| models::NewTag {
+            user_id: owner_id,
+            name,
+        })
+        .collect();
+    diesel::insert_into(tags::table)
+        .values(&new_tags)
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+
+    let tag_ids = tags::table
+        .select(tags::id)
+        .filter(tags::user_id.eq(owner_id))
+        .filter(tags::name.eq_any(tag_names))
+        .load::<i32>(conn)?;
+    let links: Vec<models::MemoryTag> = tag_ids
+        .into_iter()
+        .map(|tag_id| models::MemoryTag {
+            memory_id: tagged_memory_id,
+            tag_id,
+        })
+        .collect();
+    diesel::insert_into(memory_tags::table)
+        .values(&links)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Tag names of every memory from `tagged_memory_ids` that has tags.
+pub fn get_memory_tags(
+    tagged_memory_ids: &[i32],
+    conn: &PgConnection,
+) -> Result<BTreeMap<i32, Vec<String>>, diesel::result::Error> {
+    use crate::schema::{memory_tags, tags};
+
+    let pairs = memory_tags::table
+        .inner_join(tags::table)
+        .filter(memory_tags::memory_id.eq_any(tagged_memory_ids))
+        .select((memory_tags::memory_id, tags::name))
+        .order(tags::name.asc())
+        .load::<(i32, String)>(conn)?;
+
+    let mut result: BTreeMap<i32, Vec<String>> = BTreeMap::new();
+    for (tagged_memory_id, tag_name) in pairs {
+        result.entry(tagged_memory_id).or_default().push(tag_name);
+    }
+
+    Ok(result)
+}
+
+/// Deletes memory with its schedules and their history, returns `false` when there is no such memory.
+pub fn delete_memory(memory_id: i32, conn: &PgConnection) -> Result<bool, diesel::result::Error> {
+    use crate::schema::{memories, memory_tags, reviews, schedules};
+
+    conn.transaction(|| {
+        diesel::delete(memory_tags::table.filter(memory_tags::memory_id.eq(memory_id)))
+            .execute(conn)?;
+        let memory_schedules = schedules::table
+            .select(schedules::id)
+            .filter(schedules::memory_id.eq(memory_id));
+        diesel::delete(reviews::table.filter(reviews::schedule_id.eq_any(memory_schedules)))
+            .execute(conn)?;
+        diesel::delete(schedules::table.filter(schedules::memory_id.eq(memory_id)))
+            .execute(conn)?;
+        let deleted =
+            diesel::delete(memories::table.filter(memories::id.eq(memory_id))).execute(conn)?;
+
+        Ok(deleted > 0)
+    })
+}
+
+/// Applies `action` to all cards of memory which are not retired, returns `None` when there is no such memory.
+pub fn change_schedule(
+    changed_memory_id: i32,
+    action: models::ScheduleAction,
+    phase_sets: &PhaseSets,
+    conn: &PgConnection,
+) -> Result<Option<Vec<models::Schedule>>, failure::Error> {
+    conn.transaction::<Option<Vec<models::Schedule>>, failure::Error, _>(|| {
+        let memory_cards = lock_memory_schedules(changed_memory_id, conn).map_err(|_| DbError)?;
+        if memory_cards.is_empty() {
+            return Ok(None);
+        }
+
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(|_| TimeError)?
+            .as_secs() as i64;
+        let mut updated = Vec::with_capacity(memory_cards.len());
+        for changed in memory_cards {
+            let state = ScheduleState::from(&changed.schedule);
+            let phases = phase_sets.for_memory(&changed.memory_with_user);
+            let (new_state, new_status) = match action {
+                models::ScheduleAction::Pause => (state, models::ScheduleStatus::Paused),
+                models::ScheduleAction::Resume => {
+                    let kind = AlgorithmKind::resolve(
+                        &changed.memory_with_user.memory,
+                        &changed.memory_with_user.user,
+                    )?;
+                    let resumed = scheduling::algorithm(kind, phases).after_resume(&state, now);
+                    (resumed, models::ScheduleStatus::Active)
+                }
+                models::ScheduleAction::Reset => (
+                    ScheduleState::new(phases.first_number(), now),
+                    models::ScheduleStatus::Active,
+                ),
+            };
+
+            let new_state = delivery::plan_next_run(new_state, &changed.memory_with_user.user);
+            update_schedule_state(changed.schedule.id, &new_state, conn).map_err(|_| DbError)?;
+            updated.push(
+                set_schedule_status(changed.schedule.id, new_status, conn).map_err(|_| DbError)?,
+            );
+        }
+
+        Ok(Some(updated))
+    })
+}
+
+/// Applies `action` to schedules of all user's memories with `tag`, returns how many memories were changed.
+/// Pause touches only memories with active cards and resume only ones with paused cards.
+pub fn change_tagged_schedules(
+    owner_id: i32,
+    tag: &str,
+    action: models::ScheduleAction,
+    phase_sets: &PhaseSets,
+    conn: &PgConnection,
+) -> Result<usize, failure::Error> {
+    use crate::schema::{memory_tags, schedules, tags};
+
+    conn.transaction::<usize, failure::Error, _>(|| {
+        let tagged_memories = memory_tags::table
+            .inner_join(tags::table)
+            .filter(tags::user_id.eq(owner_id).and(tags::name.eq(tag)))
+            .select(memory_tags::memory_id);
+        let mut query = schedules::table
+            .select(schedules::memory_id)
+            .distinct()
+            .filter(schedules::memory_id.eq_any(tagged_memories))
+            .filter(schedules::status.ne(models::ScheduleStatus::Retired.as_str()))
+            .into_boxed();
+        query = match action {
+            models::ScheduleAction::Pause => {
+                query.filter(schedules::status.eq(models::ScheduleStatus::Active.as_str()))
+            }
+            models::ScheduleAction::Resume => {
+                query.filter(schedules::status.eq(models::ScheduleStatus::Paused.as_str()))
+            }
+            models::ScheduleAction::Reset => query,
+        };
+        let memory_ids = query.load::<i32>(conn).map_err(|_| DbError)?;
+
+        for changed_memory_id in memory_ids.iter() {
+            change_schedule(*changed_memory_id, action, phase_sets, conn)?;
+        }
+
+        Ok(memory_ids.len())
+    })
+}
+
+pub fn insert_deck(
+    new_deck: &models::NewDeck,
+    conn: &PgConnection,
+) -> Result<models::Deck, diesel::result::Error> {
+    use crate::schema::decks::dsl::*;
+
+    diesel::insert_into(decks)
+        .values(new_deck)
+        .get_result::<models::Deck>(conn)
+}
+
+pub fn get_deck(
+    deck_id: i32,
+    conn: &PgConnection,
+) -> Result<Option<models::Deck>, diesel::result::Error> {
+    use crate::schema::decks::dsl::*;
+
+    decks
+        .filter(id.eq(deck_id))
+        .first::<models::Deck>(conn)
+        .optional()
+}
+
+/// All decks of user ordered by name, `None` when there is no such user.
+pub fn get_user_decks(
+    owner_id: i32,
+    conn: &PgConnection,
+) -> Result<Option<Vec<models::Deck>>, diesel::result::Error> {
+    use crate::schema::decks::dsl::*;
+
+    if get_user(owner_id, conn)?.is_none() {
+        return Ok(None);
+    }
+
+    let result = decks
+        .filter(user_id.eq(owner_id))
+        .order(name.asc())
+        .load::<models::Deck>(conn)?;
+
+    Ok(Some(result))
+}
+
+pub fn update_deck(
+    deck_id: i32,
+    changes: &models::DeckChanges,
+    conn: &PgConnection,
+) -> Result<Option<models::Deck>, diesel::result::Error> {
+    use crate::schema::decks::dsl::*;
+
+    if changes.name.is_some()
+        || changes.phase_set.is_some()
+        || changes.notifications_enabled.is_some()
+    {
+        diesel::update(decks.filter(id.eq(deck_id)))
+            .set(changes)
+            .execute(conn)?;
+    }
+
+    get_deck(deck_id, conn)
+}
+
+/// Deletes deck keeping its memories out of any deck, returns `false` when there is no such deck.
+pub fn delete_deck(deck_id: i32, conn: &PgConnection) -> Result<bool, diesel::result::Error> {
+    use crate::schema::decks::dsl::*;
+
+    let deleted = diesel::delete(decks.filter(id.eq(deck_id))).execute(conn)?;
+
+    Ok(deleted > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db;
+
+    fn now() -> i64 {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64
+    }
+
+    /// Ids of schedules of `memory` claimed by `owner` at `at_secs` with a minute long lease.
+    fn claim(memory: i32, owner: &str, at_secs: i64, conn: &PgConnection) -> Vec<i32> {
+        claim_schedulers(at_secs, owner, at_secs + 60, 1000, conn)
+            .unwrap()
+            .into_iter()
+            .filter(|s| s.schedule.memory_id == memory)
+            .map(|s| s.schedule.id)
+            .collect()
+    }
+
+    #[test]
+    #[ignore]
+    fn leased_schedule_is_not_claimed_again() {
+        let conn = test_db::connection();
+        let (user_id, _) = test_db::user("leased@example.com", &conn);
+        let memory = test_db::memory(user_id, "ownership", &conn);
+        let at = now();
+
+        let first = claim(memory, "first", at, &conn);
+        assert_eq!(first.len(), 1);
+        assert!(claim(memory, "second", at, &conn).is_empty());
+        assert!(claim(memory, "second", at + 60, &conn).is_empty());
+    }
+
+    #[test]
+    #[ignore]
+    fn expired_lease_is_claimed_by_another_owner() {
+        let conn = test_db::connection();
+        let (user_id, _) = test_db::user("expired@example.com", &conn);
+        let memory = test_db::memory(user_id, "ownership", &conn);
+        let at = now();
+
+        let schedule_id = claim(memory, "first", at, &conn)[0];
+        assert_eq!(claim(memory, "second", at + 61, &conn), vec![schedule_id]);
+
+        // the first owner lost its lease and can't save the delivery
+        let state = ScheduleState::new(2, at + 900);
+        assert!(complete_lease(schedule_id, "first", &state, &conn)
+            .unwrap()
+            .is_none());
+        assert!(fail_lease(schedule_id, "first", "timeout", None, &conn)
+            .unwrap()
+            .is_none());
+
+        let completed = complete_lease(schedule_id, "second", &state, &conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!(completed.phase_number, 2);
+        assert_eq!(completed.locked_by, None);
+        assert_eq!(completed.locked_until, None);
+    }
+
+    #[test]
+    #[ignore]
+    fn failed_lease_is_released_for_retry() {
+        let conn = test_db::connection();
+        let (user_id, _) = test_db::user("retried@example.com", &conn);
+        let memory = test_db::memory(user_id, "ownership", &conn);
+        let at = now();
+
+        let schedule_id = claim(memory, "first", at, &conn)[0];
+        let failed = fail_lease(schedule_id, "first", "timeout", Some(at + 30), &conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!(failed.attempts, 1);
+        assert_eq!(failed.last_error.as_deref(), Some("timeout"));
+        assert_eq!(failed.locked_by, None);
+        assert!(claim(memory, "second", at + 29, &conn).is_empty());
+        assert_eq!(claim(memory, "second", at + 30, &conn), vec![schedule_id]);
+    }
+
+    fn orphaned(set_name: &str, new_phases: &[(i32, i64)], conn: &PgConnection) -> usize {
+        match replace_phase_set(set_name, new_phases, conn) {
+            Ok(_) => 0,
+            Err(err) => err.downcast::<OrphanedSchedules>().unwrap().count,
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn phase_set_is_not_replaced_under_schedules_of_its_users() {
+        use crate::schema::{memories, users};
+
+        let conn = test_db::connection();
+        let (default_user, _) = test_db::user("default-set@example.com", &conn);
+        let (cram_user, _) = test_db::user("cram-set@example.com", &conn);
+        diesel::update(users::table.filter(users::id.eq(cram_user)))
+            .set(users::phase_set.eq("cram"))
+            .execute(&conn)
+            .unwrap();
+        test_db::memory(default_user, "ownership", &conn);
+        test_db::memory(cram_user, "borrowing", &conn);
+
+        assert_eq!(orphaned("cram", &[(2, 0), (3, 60)], &conn), 1);
+        assert_eq!(orphaned("vocab", &[(2, 0)], &conn), 0);
+        assert_eq!(orphaned(DEFAULT_PHASE_SET, &[(2, 0)], &conn), 1);
+
+        // schedules of unknown set are on the default one
+        diesel::update(users::table.filter(users::id.eq(cram_user)))
+            .set(users::phase_set.eq("removed"))
+            .execute(&conn)
+            .unwrap();
+        assert_eq!(orphaned("cram", &[(2, 0), (3, 60)], &conn), 0);
+        assert_eq!(orphaned(DEFAULT_PHASE_SET, &[(2, 0)], &conn), 2);
+
+        // set of deck wins over the one of its owner
+        let deck = insert_deck(
+            &models::NewDeck {
+                user_id: cram_user,
+                name: "Vocabulary",
+                phase_set: Some("vocab"),
+                notifications_enabled: true,
+            },
+            &conn,
+        )
+        .unwrap();
+        diesel::update(memories::table.filter(memories::user_id.eq(cram_user)))
+            .set(memories::deck_id.eq(deck.id))
+            .execute(&conn)
+            .unwrap();
+        assert_eq!(orphaned("vocab", &[(2, 0)], &conn), 1);
+        assert_eq!(orphaned(DEFAULT_PHASE_SET, &[(2, 0)], &conn), 1);
+    }
+
+    #[test]
+    #[ignore]
+    fn failed_import_row_is_rolled_back_alone_with_its_reason() {
+        let conn = test_db::connection();
+        let (user_id, _) = test_db::user("import@example.com", &conn);
+        let phase_sets = get_phase_sets(&conn).unwrap();
+        let new_memory = |memory_text| models::NewMemory {
+            user_id,
+            topic: None,
+            text: memory_text,
+            algorithm: None,
+            deck_id: None,
+            format: "plain",
+            back: None,
+        };
+        let tag_names = vec!["rust".to_string()];
+        let rows = vec![
+            (new_memory("ownership"), tag_names.as_slice()),
+            // postgres doesn't keep zero bytes in text
+            (new_memory("broken\0text"), tag_names.as_slice()),
+            (
+                new_memory("{{c1::borrowing}} {{c2::lifetimes}}"),
+                tag_names.as_slice(),
+            ),
+        ];
+
+        let saved = import_memories(user_id, None, &rows, &phase_sets, &conn).unwrap();
+        assert!(saved[0].is_ok());
+        let reason = saved[1].as_ref().unwrap_err().to_string();
+        assert!(reason.contains("0x00"), "unexpected reason '{}'", reason);
+        let cloze = *saved[2].as_ref().unwrap();
+        assert_eq!(get_memory_tags(&[cloze], &conn).unwrap()[&cloze], tag_names);
+        assert_eq!(
+            get_memory(cloze, &conn).unwrap().unwrap().schedules.len(),
+            2
+        );
+        let (memories, total) = get_user_memories(user_id, None, None, 0, 10, &conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!((memories.len(), total), (2, 2));
+    }
+
+    #[test]
+    #[ignore]
+    fn restored_export_has_the_same_progress() {
+        let conn = test_db::connection();
+        let (exporter, _) = test_db::user("exporter@example.com", &conn);
+        let (restorer, _) = test_db::user("restorer@example.com", &conn);
+        let phase_sets = get_phase_sets(&conn).unwrap();
+        let deck = insert_deck(
+            &models::NewDeck {
+                user_id: exporter,
+                name: "Spanish",
+                phase_set: None,
+                notifications_enabled: false,
+            },
+            &conn,
+        )
+        .unwrap();
+        let cloze = models::NewMemory {
+            user_id: exporter,
+            topic: Some("verbs"),
+            text: "{{c1::ser}} and {{c2::estar}}",
+            algorithm: None,
+            deck_id: Some(deck.id),
+            format: "plain",
+            back: None,
+        };
+        let cloze = insert_reminder(&cloze, &["es".to_string()], &phase_sets, &conn).unwrap();
+        let plain = test_db::memory(exporter, "ownership", &conn);
+        review_memory(cloze, Some(2), Grade::Again, &phase_sets, &conn).unwrap();
+        review_memory(plain, None, Grade::Easy, &phase_sets, &conn).unwrap();
+
+        let exported = get_user_export(exporter, &conn).unwrap().unwrap();
+        let reviews = exported.memories.iter().flat_map(|m| m.cards.iter());
+        assert_eq!(reviews.map(|c| c.history.len()).sum::<usize>(), 2);
+        let restored = restore_memories(restorer, &exported, &phase_sets, &conn).unwrap();
+        assert!(restored.iter().all(|r| r.is_ok()));
+        assert_eq!(get_user_export(restorer, &conn).unwrap().unwrap(), exported);
+    }
+
+    #[test]
+    #[ignore]
+    fn restored_memory_is_checked_against_its_cards() {
+        let conn = test_db::connection();
+        let (exporter, _) = test_db::user("checked-exporter@example.com", &conn);
+        let (restorer, _) = test_db::user("checked-restorer@example.com", &conn);
+        let phase_sets = get_phase_sets(&conn).unwrap();
+        test_db::memory(exporter, "ownership", &conn);
+        let mut export = get_user_export(exporter, &conn).unwrap().unwrap();
+        let valid = export.memories.pop().unwrap();
+        let broken = |change: &dyn Fn(&mut ExportedMemory)| {
+            let mut memory = valid.clone();
+            change(&mut memory);
+            memory
+        };
+        export.memories = vec![
+            broken(&|m| m.deck = Some("missing".to_string())),
+            broken(&|m| m.cards[0].card = 1),
+            broken(&|m| m.cards.push(m.cards[0].clone())),
+            broken(&|m| m.cards[0].phase_number = 1000),
+        ];
+
+        let restored = restore_memories(restorer, &export, &phase_sets, &conn).unwrap();
+        let reasons: Vec<String> = restored
+            .iter()
+            .map(|r| r.as_ref().unwrap_err().to_string())
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "deck 'missing' is not among exported decks",
+                "memory has no card '1'",
+                "card '0' is exported twice",
+                "phase '1000' of card '0' isn't in the memory's phase set",
+            ]
+        );
+        assert!(get_user_export(restorer, &conn)
+            .unwrap()
+            .unwrap()
+            .memories
+            .is_empty());
+    }
+
+    #[test]
+    #[ignore]
+    fn card_is_not_reviewed_while_its_reminder_is_delivered() {
+        let conn = test_db::connection();
+        let (user_id, _) = test_db::user("reviewed@example.com", &conn);
+        let memory = test_db::memory(user_id, "ownership", &conn);
+        let phase_sets = get_phase_sets(&conn).unwrap();
+        let at = now();
+
+        let schedule_id = claim(memory, "first", at, &conn)[0];
+        let err = review_card(schedule_id, Grade::Good, &phase_sets, &conn).unwrap_err();
+        assert!(err.downcast_ref::<ScheduleLeased>().is_some(), "{}", err);
+        assert!(get_history(memory, &conn).unwrap().unwrap().is_empty());
+
+        let delivered = ScheduleState::new(2, at + 900);
+        complete_lease(schedule_id, "first", &delivered, &conn)
+            .unwrap()
+            .unwrap();
+        assert!(review_card(schedule_id, Grade::Good, &phase_sets, &conn)
+            .unwrap()
+            .is_some());
+        let history = get_history(memory, &conn).unwrap().unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].grade.as_deref(), Some("good"));
+    }
+
+    #[test]
+    #[ignore]
+    fn expired_verification_token_is_refused() {
+        let conn = test_db::connection();
+        let (user_id, _) = test_db::user("expired-token@example.com", &conn);
+        let new_token = models::NewVerificationToken {
+            user_id,
+            token_hash: "expired-token-hash",
+        };
+        replace_verification_token(&new_token, &conn).unwrap();
+
+        let at = now();
+        assert!(verify_user("expired-token-hash", at + 60, &conn)
+            .unwrap()
+            .is_none());
+        let verified = verify_user("expired-token-hash", at - 60, &conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!(verified.id, user_id);
+        assert!(verify_user("expired-token-hash", at - 60, &conn)
+            .unwrap()
+            .is_none());
+    }
+
+    fn next_run_of(memory: i32, conn: &PgConnection) -> Option<i64> {
+        use crate::schema::schedules::dsl::*;
+
+        schedules
+            .select(next_run)
+            .filter(memory_id.eq(memory))
+            .first(conn)
+            .unwrap()
+    }
+
+    #[test]
+    #[ignore]
+    fn pending_reminders_move_to_digest_slot() {
+        let conn = test_db::connection();
+        let (user_id, _) = test_db::user("realigned@example.com", &conn);
+        let leased = test_db::memory(user_id, "leased", &conn);
+        let leased_due = next_run_of(leased, &conn);
+        let leased_id = claim(leased, "first", now(), &conn)[0];
+        let pending = test_db::memory(user_id, "pending", &conn);
+        let due = next_run_of(pending, &conn).unwrap();
+
+        let changes = models::UserChanges {
+            timezone: None,
+            quiet_start: None,
+            quiet_end: None,
+            delivery_mode: Some(delivery::DeliveryMode::Daily.as_str()),
+            digest_time: Some(Some(chrono::NaiveTime::from_hms(3, 0, 0))),
+            phase_set: None,
+            unsubscribed: None,
+        };
+        update_user(user_id, &changes, &conn).unwrap().unwrap();
+
+        let realigned = next_run_of(pending, &conn).unwrap();
+        assert!(realigned >= due);
+        assert_eq!(realigned % 86400, 3 * 3600);
+        // the leased reminder is planned when its delivery completes
+        assert_eq!(next_run_of(leased, &conn), leased_due);
+        let delivered = ScheduleState::new(2, due);
+        let completed = complete_lease(leased_id, "first", &delivered, &conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!(completed.next_run, Some(realigned));
+    }
+
+    #[test]
+    #[ignore]
+    fn digest_is_claimed_whole() {
+        use crate::schema::{schedules, users};
+
+        let conn = test_db::connection();
+        let (user_id, _) = test_db::user("digest@example.com", &conn);
+        let memories: Vec<i32> = (0..3)
+            .map(|n| test_db::memory(user_id, &n.to_string(), &conn))
+            .collect();
+        diesel::update(users::table.filter(users::id.eq(user_id)))
+            .set(users::delivery_mode.eq(delivery::DeliveryMode::Hourly.as_str()))
+            .execute(&conn)
+            .unwrap();
+        // the oldest due reminders, so the limit cuts exactly this digest
+        diesel::update(schedules::table.filter(schedules::memory_id.eq_any(&memories)))
+            .set(schedules::next_run.eq(1))
+            .execute(&conn)
+            .unwrap();
+
+        let at = now();
+        let claimed = claim_schedulers(at, "first", at + 60, 1, &conn).unwrap();
+        assert_eq!(claimed.len(), 3);
+        assert!(claimed
+            .iter()
+            .all(|s| memories.contains(&s.schedule.memory_id)));
+    }
+
+    #[test]
+    #[ignore]
+    fn concurrent_claims_skip_locked_rows() {
+        // rows must be committed to be seen by both connections
+        let setup = test_db::committing_connection();
+        let email = format!("skip-locked-{}@example.com", std::process::id());
+        let (user_id, _) = test_db::user(&email, &setup);
+        let memories: Vec<i32> = (0..2)
+            .map(|n| test_db::memory(user_id, &n.to_string(), &setup))
+            .collect();
+        let at = now();
+
+        // rows claimed by the first instance stay locked until its transaction ends
+        let first = test_db::connection();
+        let second = test_db::committing_connection();
+        let first_claimed = claim_schedulers(at, "first", at + 60, 1, &first).unwrap();
+        let second_claimed = claim_schedulers(at, "second", at + 60, 1000, &second).unwrap();
+        drop(first);
+
+        for memory in memories.iter() {
+            delete_memory(*memory, &setup).unwrap();
+        }
+        {
+            use crate::schema::{api_keys, users};
+            diesel::delete(api_keys::table.filter(api_keys::user_id.eq(user_id)))
+                .execute(&setup)
+                .unwrap();
+            diesel::delete(users::table.filter(users::id.eq(user_id)))
+                .execute(&setup)
+                .unwrap();
+        }
+
+        let first_ids: Vec<i32> = first_claimed.iter().map(|s| s.schedule.id).collect();
+        assert_eq!(first_ids.len(), 1);
+        assert!(second_claimed
+            .iter()
+            .all(|s| !first_ids.contains(&s.schedule.id)));
+        let ours = |claimed: &[models::ScheduleWithMemoryAndUser]| {
+            claimed
+                .iter()
+                .filter(|s| memories.contains(&s.schedule.memory_id))
+                .count()
+        };
+        assert_eq!(ours(&first_claimed) + ours(&second_claimed), 2);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::models::User;
+use crate::quiet_hours;
+use crate::scheduling::ScheduleState;
+use chrono::{Duration, NaiveTime, TimeZone, Timelike};
+use chrono_tz::Tz;
+use log::error;
+use std::str::FromStr;
+
+#[derive(Debug, Fail)]
+pub enum DeliveryError {
+    #[fail(display = "unknown delivery mode '{}'", name)]
+    UnknownMode { name: String },
+}
+
+/// How user wants to receive reminders.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryMode {
+    /// Every reminder in a separate message as soon as it is due.
+    Immediate,
+    /// Reminders due within an hour in one message at the start of the next hour.
+    Hourly,
+    /// Reminders due within a day in one message at user's digest time.
+    Daily,
+}
+
+impl DeliveryMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeliveryMode::Immediate => "immediate",
+            DeliveryMode::Hourly => "hourly",
+            DeliveryMode::Daily => "daily",
+        }
+    }
+}
+
+impl FromStr for DeliveryMode {
+    type Err = DeliveryError;
+
+    fn from_str(name: &str) -> Result<DeliveryMode, DeliveryError> {
+        match name {
+            "immediate" => Ok(DeliveryMode::Immediate),
+            "hourly" => Ok(DeliveryMode::Hourly),
+            "daily" => Ok(DeliveryMode::Daily),
+            _ => Err(DeliveryError::UnknownMode {
+                name: name.to_string(),
+            }),
+        }
+    }
+}
+
+/// Daily digest time when user didn't choose one.
+pub fn default_digest_time() -> NaiveTime {
+    NaiveTime::from_hms(9, 0, 0)
+}
+
+/// First digest slot at or after `at_secs` in `timezone`.
+pub fn digest_slot(mode: DeliveryMode, digest_time: NaiveTime, timezone: Tz, at_secs: i64) -> i64 {
+    let local = timezone.timestamp(at_secs, 0);
+    let slot = match mode {
+        DeliveryMode::Immediate => return at_secs,
+        DeliveryMode::Hourly => {
+            if local.minute() == 0 && local.second() == 0 {
+                return at_secs;
+            }
+            local
+                .date()
+                .and_hms_opt(local.hour(), 0, 0)
+                .unwrap_or(local)
+                + Duration::hours(1)
+        }
+        DeliveryMode::Daily => {
+            let today = local.date().and_time(digest_time);
+            match today {
+                Some(slot) if slot >= local => slot,
+                _ => (local.date() + Duration::days(1))
+                    .and_time(digest_time)
+                    // digest time doesn't exist tomorrow because of daylight saving switch
+                    .unwrap_or_else(|| local + Duration::days(1)),
+            }
+        }
+    };
+
+    slot.timestamp().max(at_secs)
+}
+
+/// Moves next run of user's schedule to user's digest slot and out of user's quiet hours.
+pub fn plan_next_run(state: ScheduleState, user: &User) -> ScheduleState {
+    let state = match next_digest_run(state.next_run, user) {
+        Ok(next_run) => ScheduleState { next_run, ..state },
+        Err(err) => {
+            error!(
+                "ignore delivery mode of user with id '{}', reason: '{}'",
+                user.id, err
+            );
+            state
+        }
+    };
+
+    quiet_hours::defer_next_run(state, user)
+}
+
+fn next_digest_run(next_run: Option<i64>, user: &User) -> Result<Option<i64>, failure::Error> {
+    let mode: DeliveryMode = user.delivery_mode.parse()?;
+    if mode == DeliveryMode::Immediate {
+        return Ok(next_run);
+    }
+
+    let timezone = quiet_hours::parse_timezone(&user.timezone)?;
+    let digest_time = user.digest_time.unwrap_or_else(default_digest_time);
+    Ok(next_run.map(|at| digest_slot(mode, digest_time, timezone, at)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(tz: Tz, d: u32, h: u32, min: u32) -> i64 {
+        tz.ymd(2020, 4, d).and_hms(h, min, 0).timestamp()
+    }
+
+    #[test]
+    fn hourly_slot_is_the_next_hour() {
+        let tz = chrono_tz::Asia::Kolkata;
+        let slot = |at| digest_slot(DeliveryMode::Hourly, default_digest_time(), tz, at);
+        assert_eq!(slot(ts(tz, 20, 10, 15)), ts(tz, 20, 11, 0));
+        assert_eq!(slot(ts(tz, 20, 11, 0)), ts(tz, 20, 11, 0));
+        assert_eq!(slot(ts(tz, 20, 23, 59)), ts(tz, 21, 0, 0));
+    }
+
+    #[test]
+    fn daily_slot_is_the_next_digest_time() {
+        let tz = chrono_tz::Europe::Moscow;
+        let time = NaiveTime::from_hms(8, 30, 0);
+        let slot = |at| digest_slot(DeliveryMode::Daily, time, tz, at);
+        assert_eq!(slot(ts(tz, 20, 7, 0)), ts(tz, 20, 8, 30));
+        assert_eq!(slot(ts(tz, 20, 8, 30)), ts(tz, 20, 8, 30));
+        assert_eq!(slot(ts(tz, 20, 9, 0)), ts(tz, 21, 8, 30));
+    }
+}
@@ -0,0 +1,283 @@
+use crate::models::{Deck, Memory, Review, Schedule};
+use csv::Writer;
+use std::collections::BTreeMap;
+
+const CSV_HEADER: [&str; 10] = [
+    "topic",
+    "text",
+    "back",
+    "tags",
+    "deck",
+    "card",
+    "status",
+    "phase_number",
+    "next_run",
+    "history",
+];
+
+/// Format of exported file.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// Everything needed to restore memories with `json` import.
+    Json,
+    /// One row per card of every memory.
+    Csv,
+}
+
+/// Memories of a user with their decks, tags and progress.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Export {
+    #[serde(default)]
+    pub decks: Vec<ExportedDeck>,
+    pub memories: Vec<ExportedMemory>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ExportedDeck {
+    pub name: String,
+    pub phase_set: Option<String>,
+    pub notifications_enabled: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportedMemory {
+    pub topic: Option<String>,
+    pub text: String,
+    pub back: Option<String>,
+    pub format: String,
+    pub algorithm: Option<String>,
+    /// Name of the deck.
+    pub deck: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub cards: Vec<ExportedCard>,
+}
+
+/// Schedule of a card with the whole state of its scheduling algorithm.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportedCard {
+    pub card: i32,
+    pub status: String,
+    pub phase_number: i32,
+    pub next_run: Option<i64>,
+    pub ease_factor: f64,
+    pub interval_secs: i64,
+    pub repetitions: i32,
+    pub stability: Option<f64>,
+    pub difficulty: Option<f64>,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub history: Vec<ExportedReview>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportedReview {
+    pub phase_number: i32,
+    pub sent_at: i64,
+    pub channel: String,
+    pub delivery_status: String,
+    pub grade: Option<String>,
+    pub response_latency: Option<i64>,
+}
+
+impl Export {
+    /// Puts schedules, reviews and tags (by memory id) under memories they belong to.
+    pub fn new(
+        decks: Vec<Deck>,
+        memories: Vec<Memory>,
+        schedules: Vec<Schedule>,
+        reviews: Vec<Review>,
+        mut tags: BTreeMap<i32, Vec<String>>,
+    ) -> Export {
+        let mut history: BTreeMap<i32, Vec<ExportedReview>> = BTreeMap::new();
+        for review in reviews {
+            history
+                .entry(review.schedule_id)
+                .or_default()
+                .push(ExportedReview {
+                    phase_number: review.phase_number,
+                    sent_at: review.sent_at,
+                    channel: review.channel,
+                    delivery_status: review.delivery_status,
+                    grade: review.grade,
+                    response_latency: review.response_latency,
+                });
+        }
+
+        let mut cards: BTreeMap<i32, Vec<ExportedCard>> = BTreeMap::new();
+        for schedule in schedules {
+            cards
+                .entry(schedule.memory_id)
+                .or_default()
+                .push(ExportedCard {
+                    history: history.remove(&schedule.id).unwrap_or_default(),
+                    card: schedule.card,
+                    status: schedule.status,
+                    phase_number: schedule.phase_number,
+                    next_run: schedule.next_run,
+                    ease_factor: schedule.ease_factor,
+                    interval_secs: schedule.interval_secs,
+                    repetitions: schedule.repetitions,
+                    stability: schedule.stability,
+                    difficulty: schedule.difficulty,
+                    attempts: schedule.attempts,
+                    last_error: schedule.last_error,
+                });
+        }
+
+        let deck_names: BTreeMap<i32, String> =
+            decks.iter().map(|d| (d.id, d.name.clone())).collect();
+        let memories = memories
+            .into_iter()
+            .map(|memory| ExportedMemory {
+                deck: memory.deck_id.and_then(|id| deck_names.get(&id).cloned()),
+                tags: tags.remove(&memory.id).unwrap_or_default(),
+                cards: cards.remove(&memory.id).unwrap_or_default(),
+                topic: memory.topic,
+                text: memory.text,
+                back: memory.back,
+                format: memory.format,
+                algorithm: memory.algorithm,
+            })
+            .collect();
+
+        Export {
+            decks: decks
+                .into_iter()
+                .map(|deck| ExportedDeck {
+                    name: deck.name,
+                    phase_set: deck.phase_set,
+                    notifications_enabled: deck.notifications_enabled,
+                })
+                .collect(),
+            memories,
+        }
+    }
+
+    /// One row per card, tags are separated by spaces and history is a list of `sent_at:delivery_status[:grade]`.
+    pub fn to_csv(&self) -> Result<String, failure::Error> {
+        let mut writer = Writer::from_writer(Vec::new());
+        writer.write_record(CSV_HEADER)?;
+        for memory in self.memories.iter() {
+            for card in memory.cards.iter() {
+                let history: Vec<String> = card
+                    .history
+                    .iter()
+                    .map(|review| match &review.grade {
+                        Some(grade) => {
+                            format!("{}:{}:{}", review.sent_at, review.delivery_status, grade)
+                        }
+                        None => format!("{}:{}", review.sent_at, review.delivery_status),
+                    })
+                    .collect();
+                writer.write_record([
+                    memory.topic.as_deref().unwrap_or(""),
+                    &memory.text,
+                    memory.back.as_deref().unwrap_or(""),
+                    &memory.tags.join(" "),
+                    memory.deck.as_deref().unwrap_or(""),
+                    &card.card.to_string(),
+                    &card.status,
+                    &card.phase_number.to_string(),
+                    &card.next_run.map(|at| at.to_string()).unwrap_or_default(),
+                    &history.join(" "),
+                ])?;
+            }
+        }
+
+        Ok(String::from_utf8(writer.into_inner()?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn export() -> Export {
+        let deck = Deck {
+            id: 3,
+            user_id: 1,
+            name: "Rust".to_string(),
+            phase_set: None,
+            notifications_enabled: true,
+        };
+        let memory = Memory {
+            id: 5,
+            user_id: 1,
+            topic: Some("Borrowing".to_string()),
+            text: "{{c1::Rust}} checks {{c2::borrows}}".to_string(),
+            algorithm: Some("sm2".to_string()),
+            deck_id: Some(3),
+            format: "plain".to_string(),
+            back: None,
+        };
+        let schedule = |id, card, next_run| Schedule {
+            id,
+            memory_id: 5,
+            phase_number: 2,
+            next_run,
+            ease_factor: 2.5,
+            interval_secs: 86400,
+            repetitions: 1,
+            stability: None,
+            difficulty: None,
+            locked_by: None,
+            locked_until: None,
+            status: "active".to_string(),
+            attempts: 0,
+            last_error: None,
+            card,
+        };
+        let review = Review {
+            id: 9,
+            schedule_id: 8,
+            phase_number: 1,
+            sent_at: 1000,
+            channel: "email".to_string(),
+            delivery_status: "sent".to_string(),
+            grade: Some("good".to_string()),
+            response_latency: Some(60),
+        };
+        let mut tags = BTreeMap::new();
+        tags.insert(5, vec!["lang".to_string(), "rust".to_string()]);
+
+        Export::new(
+            vec![deck],
+            vec![memory],
+            vec![schedule(7, 1, Some(2000)), schedule(8, 2, None)],
+            vec![review],
+            tags,
+        )
+    }
+
+    #[test]
+    fn cards_and_history_are_put_under_their_memory() {
+        let export = export();
+        let memory = &export.memories[0];
+        assert_eq!(memory.deck.as_deref(), Some("Rust"));
+        assert_eq!(memory.tags, vec!["lang", "rust"]);
+        assert_eq!(memory.cards.len(), 2);
+        assert!(memory.cards[0].history.is_empty());
+        assert_eq!(memory.cards[1].history[0].grade.as_deref(), Some("good"));
+
+        let json = serde_json::to_string(&export).unwrap();
+        assert_eq!(serde_json::from_str::<Export>(&json).unwrap(), export);
+    }
+
+    #[test]
+    fn csv_has_row_per_card() {
+        let csv = export().to_csv().unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], CSV_HEADER.join(","));
+        assert_eq!(
+            lines[1],
+            "Borrowing,{{c1::Rust}} checks {{c2::borrows}},,lang rust,Rust,1,active,2,2000,"
+        );
+        assert_eq!(
+            lines[2],
+            "Borrowing,{{c1::Rust}} checks {{c2::borrows}},,lang rust,Rust,2,active,2,,1000:sent:good"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use crate::auth::{self, Admin};
+use crate::data::{ApiKeyResponse, PhaseSetSummary, ReplacePhasesRequest};
+use crate::db_actions::{self, OrphanedSchedules};
+use crate::models::{self, Phase};
+use crate::phase::{PhaseError, PhaseSets, Phases, ReloadablePhaseSets, DEFAULT_PHASE_SET};
+use crate::DbPool;
+use actix_web::error::BlockingError;
+use actix_web::{get, post, put, web, Error, HttpResponse};
+use log::error;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+#[get("/admin/failed_schedules")]
+pub async fn get_failed_schedules(
+    pool: web::Data<DbPool>,
+    _admin: Admin,
+) -> Result<HttpResponse, Error> {
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let schedules = web::block(move || db_actions::get_failed_schedules(&conn))
+        .await
+        .map_err(|e| {
+            error!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        })?;
+
+    Ok(HttpResponse::Ok().json(schedules))
+}
+
+#[post("/admin/schedules/{schedule_id}/requeue")]
+pub async fn requeue_schedule(
+    pool: web::Data<DbPool>,
+    _admin: Admin,
+    schedule_id_param: web::Path<i32>,
+) -> Result<HttpResponse, Error> {
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let schedule_id = schedule_id_param.into_inner();
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_err(|_| HttpResponse::InternalServerError().finish())?
+        .as_secs() as i64;
+    let schedule = web::block(move || db_actions::requeue_schedule(schedule_id, now, &conn))
+        .await
+        .map_err(|e| {
+            error!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        })?;
+
+    let result = match schedule {
+        None => HttpResponse::NotFound().body(format!(
+            "No failed schedule found with id '{}'",
+            schedule_id
+        )),
+        Some(s) => HttpResponse::Ok().json(s),
+    };
+
+    Ok(result)
+}
+
+/// Re-reads phase sets from DB, invalid sets are reported and the running ones are kept.
+#[post("/admin/phases/reload")]
+pub async fn reload_phases(
+    pool: web::Data<DbPool>,
+    _admin: Admin,
+    phase_sets: web::Data<Arc<ReloadablePhaseSets>>,
+) -> Result<HttpResponse, Error> {
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let reloaded = web::block(move || phase_sets.reload(&conn))
+        .await
+        .map_err(phases_error)?;
+
+    Ok(HttpResponse::Ok().json(summary(&reloaded)))
+}
+
+#[get("/admin/phases")]
+pub async fn get_phases(pool: web::Data<DbPool>, _admin: Admin) -> Result<HttpResponse, Error> {
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let phases = web::block(move || db_actions::get_all_phases(&conn))
+        .await
+        .map_err(|e| {
+            error!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        })?;
+
+    Ok(HttpResponse::Ok().json(phases))
+}
+
+/// Replaces one phase set, running scheduler switches to it right after commit.
+#[put("/admin/phases")]
+pub async fn replace_phases(
+    pool: web::Data<DbPool>,
+    _admin: Admin,
+    phase_sets: web::Data<Arc<ReloadablePhaseSets>>,
+    request: web::Json<ReplacePhasesRequest>,
+) -> Result<HttpResponse, Error> {
+    let set_name = request
+        .phase_set
+        .clone()
+        .unwrap_or_else(|| DEFAULT_PHASE_SET.to_string());
+    if request.phases.iter().any(|p| p.seconds_to_wait < 0) {
+        return Ok(HttpResponse::BadRequest().body("seconds_to_wait can't be negative"));
+    }
+    let checked = request
+        .phases
+        .iter()
+        .map(|p| Phase {
+            id: 0,
+            number: p.number,
+            seconds_to_wait: p.seconds_to_wait,
+            phase_set: set_name.clone(),
+        })
+        .collect();
+    if let Err(err) = Phases::new(checked) {
+        return Ok(HttpResponse::BadRequest().body(err.to_string()));
+    }
+
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let replaced = web::block(move || {
+        let new_phases: Vec<(i32, i64)> = request
+            .phases
+            .iter()
+            .map(|p| (p.number, p.seconds_to_wait))
+            .collect();
+        db_actions::replace_phase_set(&set_name, &new_phases, &conn)
+    })
+    .await
+    .map_err(phases_error)?;
+
+    let replaced = Arc::new(replaced);
+    phase_sets.replace(replaced.clone());
+
+    Ok(HttpResponse::Ok().json(summary(&replaced)))
+}
+
+/// Issues a new API key to user, e.g. when the old one is lost, the old key stops working.
+#[post("/admin/users/{user_id}/api_key")]
+pub async fn issue_api_key(
+    pool: web::Data<DbPool>,
+    _admin: Admin,
+    user_id_param: web::Path<i32>,
+) -> Result<HttpResponse, Error> {
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let user_id = user_id_param.into_inner();
+    let (api_key, api_key_hash) = auth::new_secret();
+    let issued = web::block(move || {
+        let new_key = models::NewApiKey {
+            user_id,
+            key_hash: &api_key_hash,
+        };
+        db_actions::replace_api_key(&new_key, &conn)
+    })
+    .await
+    .map_err(|e| {
+        error!("{}", e);
+        HttpResponse::InternalServerError().finish()
+    })?;
+
+    let result = if issued {
+        HttpResponse::Ok().json(ApiKeyResponse { api_key })
+    } else {
+        HttpResponse::NotFound().body(format!("No user found with id '{}'", user_id))
+    };
+
+    Ok(result)
+}
+
+fn summary(phase_sets: &PhaseSets) -> Vec<PhaseSetSummary> {
+    phase_sets
+        .iter()
+        .map(|(name, phases)| PhaseSetSummary {
+            name: name.to_string(),
+            phases: phases.count,
+        })
+        .collect()
+}
+
+/// Invalid phase sets are reported as unprocessable, schedules left without phases as conflict.
+fn phases_error(err: BlockingError<failure::Error>) -> HttpResponse {
+    let err = match err {
+        BlockingError::Error(err) => err,
+        err => {
+            error!("{}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if let Some(orphaned) = err.downcast_ref::<OrphanedSchedules>() {
+        return HttpResponse::Conflict().body(orphaned.to_string());
+    }
+    match err.downcast_ref::<PhaseError>() {
+        Some(PhaseError::DbError) | None => {
+            error!("{}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+        Some(invalid) => {
+            error!("keep previous phase sets, reason: '{}'", invalid);
+            HttpResponse::UnprocessableEntity().body(invalid.to_string())
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use crate::auth::{self, AuthUser};
+use crate::data::*;
+use crate::db_actions;
+use crate::models;
+use crate::phase::ReloadablePhaseSets;
+use crate::DbPool;
+use actix_web::error::BlockingError;
+use actix_web::{delete, get, patch, post, web, Error, HttpResponse};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use log::error;
+use std::sync::Arc;
+
+#[post("/decks")]
+pub async fn create_deck(
+    pool: web::Data<DbPool>,
+    phase_sets: web::Data<Arc<ReloadablePhaseSets>>,
+    user: AuthUser,
+    request: web::Json<CreateDeckRequest>,
+) -> Result<HttpResponse, Error> {
+    auth::check_owner(&user.0, request.user_id)?;
+    if let Some(phase_set) = &request.phase_set {
+        if let Err(err) = phase_sets.current().check(phase_set) {
+            return Ok(HttpResponse::BadRequest().body(err.to_string()));
+        }
+    }
+
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let user_id = request.user_id;
+    let deck = web::block(move || {
+        if db_actions::get_user(request.user_id, &conn)?.is_none() {
+            return Ok(None);
+        }
+        let new_deck = models::NewDeck {
+            user_id: request.user_id,
+            name: &request.name,
+            phase_set: request.phase_set.as_deref(),
+            notifications_enabled: request.notifications_enabled.unwrap_or(true),
+        };
+        db_actions::insert_deck(&new_deck, &conn).map(Some)
+    })
+    .await
+    .map_err(deck_error)?;
+
+    let result = match deck {
+        None => HttpResponse::NotFound().body(format!("No user found with id '{}'", user_id)),
+        Some(d) => HttpResponse::Ok().json(d),
+    };
+
+    Ok(result)
+}
+
+#[get("/users/{user_id}/decks")]
+pub async fn get_user_decks(
+    pool: web::Data<DbPool>,
+    user: AuthUser,
+    user_id_param: web::Path<i32>,
+) -> Result<HttpResponse, Error> {
+    let user_id = user_id_param.into_inner();
+    auth::check_owner(&user.0, user_id)?;
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let decks = web::block(move || db_actions::get_user_decks(user_id, &conn))
+        .await
+        .map_err(|e| {
+            error!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        })?;
+
+    let result = match decks {
+        None => HttpResponse::NotFound().body(format!("No user found with id '{}'", user_id)),
+        Some(d) => HttpResponse::Ok().json(d),
+    };
+
+    Ok(result)
+}
+
+#[get("/decks/{deck_id}")]
+pub async fn get_deck(
+    pool: web::Data<DbPool>,
+    user: AuthUser,
+    deck_id_param: web::Path<i32>,
+) -> Result<HttpResponse, Error> {
+    let deck_id = deck_id_param.into_inner();
+    check_deck(&pool, &user, deck_id).await?;
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let deck = web::block(move || db_actions::get_deck(deck_id, &conn))
+        .await
+        .map_err(|e| {
+            error!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        })?;
+
+    Ok(deck_response(deck_id, deck))
+}
+
+#[patch("/decks/{deck_id}")]
+pub async fn update_deck(
+    pool: web::Data<DbPool>,
+    phase_sets: web::Data<Arc<ReloadablePhaseSets>>,
+    user: AuthUser,
+    deck_id_param: web::Path<i32>,
+    request: web::Json<UpdateDeckRequest>,
+) -> Result<HttpResponse, Error> {
+    let deck_id = deck_id_param.into_inner();
+    check_deck(&pool, &user, deck_id).await?;
+    if let Some(Some(phase_set)) = &request.phase_set {
+        if let Err(err) = phase_sets.current().check(phase_set) {
+            return Ok(HttpResponse::BadRequest().body(err.to_string()));
+        }
+    }
+
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let deck = web::block(move || {
+        let changes = models::DeckChanges {
+            name: request.name.as_deref(),
+            phase_set: request.phase_set.as_ref().map(|p| p.as_deref()),
+            notifications_enabled: request.notifications_enabled,
+        };
+        db_actions::update_deck(deck_id, &changes, &conn)
+    })
+    .await
+    .map_err(deck_error)?;
+
+    Ok(deck_response(deck_id, deck))
+}
+
+#[delete("/decks/{deck_id}")]
+pub async fn delete_deck(
+    pool: web::Data<DbPool>,
+    user: AuthUser,
+    deck_id_param: web::Path<i32>,
+) -> Result<HttpResponse, Error> {
+    let deck_id = deck_id_param.into_inner();
+    check_deck(&pool, &user, deck_id).await?;
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let deleted = web::block(move || db_actions::delete_deck(deck_id, &conn))
+        .await
+        .map_err(|e| {
+            error!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        })?;
+
+    let result = if deleted {
+        HttpResponse::NoContent().finish()
+    } else {
+        HttpResponse::NotFound().body(format!("No deck found with id '{}'", deck_id))
+    };
+
+    Ok(result)
+}
+
+/// Fails with 404 when there is no such deck and with 403 when it belongs to another user.
+async fn check_deck(
+    pool: &web::Data<DbPool>,
+    user: &AuthUser,
+    deck_id: i32,
+) -> Result<(), HttpResponse> {
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let deck = web::block(move || db_actions::get_deck(deck_id, &conn))
+        .await
+        .map_err(|e| {
+            error!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        })?;
+
+    match deck {
+        None => Err(HttpResponse::NotFound().body(format!("No deck found with id '{}'", deck_id))),
+        Some(d) => auth::check_owner(&user.0, d.user_id),
+    }
+}
+
+fn deck_response(deck_id: i32, deck: Option<models::Deck>) -> HttpResponse {
+    match deck {
+        None => HttpResponse::NotFound().body(format!("No deck found with id '{}'", deck_id)),
+        Some(d) => HttpResponse::Ok().json(d),
+    }
+}
+
+/// Deck names are unique per user, taken name is reported as conflict.
+fn deck_error(err: BlockingError<DieselError>) -> HttpResponse {
+    match err {
+        BlockingError::Error(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            HttpResponse::Conflict().body("Deck with this name already exists")
+        }
+        err => {
+            error!("{}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use crate::auth::{self, AuthUser};
+use crate::data::*;
+use crate::db_actions;
+use crate::export::ExportFormat;
+use crate::DbPool;
+use actix_web::{get, web, Error, HttpResponse};
+use log::error;
+
+/// All memories of user with their progress, JSON export can be restored with `format=json` import.
+#[get("/users/{user_id}/export")]
+pub async fn export_memories(
+    pool: web::Data<DbPool>,
+    user: AuthUser,
+    user_id_param: web::Path<i32>,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse, Error> {
+    let user_id = user_id_param.into_inner();
+    auth::check_owner(&user.0, user_id)?;
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let export = web::block(move || db_actions::get_user_export(user_id, &conn))
+        .await
+        .map_err(|e| {
+            error!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        })?;
+    let export = match export {
+        None => {
+            return Ok(HttpResponse::NotFound().body(format!("No user found with id '{}'", user_id)))
+        }
+        Some(e) => e,
+    };
+
+    let result = match query.format.unwrap_or(ExportFormat::Json) {
+        ExportFormat::Json => HttpResponse::Ok().json(export),
+        ExportFormat::Csv => {
+            let csv = export.to_csv().map_err(|e| {
+                error!(
+                    "fail to write export of user with id '{}', reason: '{}'",
+                    user_id, e
+                );
+                HttpResponse::InternalServerError().finish()
+            })?;
+            HttpResponse::Ok()
+                .content_type("text/csv; charset=utf-8")
+                .body(csv)
+        }
+    };
+
+    Ok(result)
+}
@@ -0,0 +1,107 @@
+use super::{confirmation_page, invalid_link};
+use crate::cards;
+use crate::db_actions;
+use crate::links::Links;
+use crate::phase::ReloadablePhaseSets;
+use crate::scheduling::Grade;
+use crate::templates::{GradeLinks, RevealContext, Templates};
+use crate::DbPool;
+use actix_web::{get, post, web, Error, HttpResponse};
+use log::error;
+use std::sync::Arc;
+
+/// Page with the answer of a card, its buttons grade the card right away.
+#[get("/reveal/cards/{schedule_id}/{signature}")]
+pub async fn reveal_card(
+    pool: web::Data<DbPool>,
+    links: web::Data<Arc<Links>>,
+    templates: web::Data<Arc<Templates>>,
+    path: web::Path<(i32, String)>,
+) -> Result<HttpResponse, Error> {
+    let (schedule_id, signature) = path.into_inner();
+    if !links.check_reveal(schedule_id, &signature) {
+        return Ok(invalid_link());
+    }
+
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let card = web::block(move || db_actions::get_card(schedule_id, &conn))
+        .await
+        .map_err(|e| {
+            error!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        })?;
+    let (schedule, memory) = match card {
+        None => {
+            return Ok(
+                HttpResponse::NotFound().body(format!("No card found with id '{}'", schedule_id))
+            )
+        }
+        Some(c) => c,
+    };
+
+    let front = cards::front(&memory, schedule.card);
+    let back = cards::back(&memory);
+    let context = RevealContext {
+        topic: memory.topic.clone(),
+        front_html: templates.text_html(&front, &memory.format),
+        front,
+        back_html: templates.text_html(&back, &memory.format),
+        back,
+        grade_links: GradeLinks::new(&links, schedule_id),
+    };
+    let page = templates.render_reveal(&context).map_err(|e| {
+        error!(
+            "fail to render answer of card with id '{}', reason: '{}'",
+            schedule_id, e
+        );
+        HttpResponse::InternalServerError().finish()
+    })?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(page))
+}
+
+#[get("/grade/cards/{schedule_id}/{grade}/{signature}")]
+pub async fn confirm_grade(
+    links: web::Data<Arc<Links>>,
+    path: web::Path<(i32, Grade, String)>,
+) -> HttpResponse {
+    let (schedule_id, grade, signature) = path.into_inner();
+    if !links.check_grade(schedule_id, grade, &signature) {
+        return invalid_link();
+    }
+
+    confirmation_page(
+        &format!("Grade this card as '{}'?", grade.as_str()),
+        "Grade",
+    )
+}
+
+/// Same as review request made with API key.
+#[post("/grade/cards/{schedule_id}/{grade}/{signature}")]
+pub async fn grade_card(
+    pool: web::Data<DbPool>,
+    phase_sets: web::Data<Arc<ReloadablePhaseSets>>,
+    links: web::Data<Arc<Links>>,
+    path: web::Path<(i32, Grade, String)>,
+) -> Result<HttpResponse, Error> {
+    let (schedule_id, grade, signature) = path.into_inner();
+    if !links.check_grade(schedule_id, grade, &signature) {
+        return Ok(invalid_link());
+    }
+
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let schedule = web::block(move || {
+        db_actions::review_card(schedule_id, grade, &phase_sets.current(), &conn)
+    })
+    .await
+    .map_err(super::memories::memory_error)?;
+
+    let result = match schedule {
+        None => HttpResponse::NotFound().body(format!("No card found with id '{}'", schedule_id)),
+        Some(_) => HttpResponse::Ok().body(format!("Grade '{}' is recorded", grade.as_str())),
+    };
+
+    Ok(result)
+}
@@ -0,0 +1,131 @@
+use crate::auth::{self, AuthUser};
+use crate::data::*;
+use crate::db_actions;
+use crate::export::Export;
+use crate::import::{self, ImportFormat};
+use crate::models;
+use crate::phase::{PhaseSets, ReloadablePhaseSets};
+use crate::DbPool;
+use actix_web::{post, web, Error, HttpResponse};
+use log::error;
+use std::sync::Arc;
+
+/// Biggest file that can be imported at once, in bytes.
+pub const MAX_FILE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Creates memories from rows of CSV or Anki export in one request, or restores JSON export of this service.
+/// Rows that can't be read or saved are reported and the rest are imported.
+#[post("/users/{user_id}/import")]
+pub async fn import_memories(
+    pool: web::Data<DbPool>,
+    phase_sets: web::Data<Arc<ReloadablePhaseSets>>,
+    user: AuthUser,
+    user_id_param: web::Path<i32>,
+    query: web::Query<ImportQuery>,
+    body: String,
+) -> Result<HttpResponse, Error> {
+    let user_id = user_id_param.into_inner();
+    auth::check_owner(&user.0, user_id)?;
+    let rows = match query.format.unwrap_or(ImportFormat::Csv) {
+        ImportFormat::Csv => import::parse_csv(&body),
+        ImportFormat::Anki => import::parse_anki(&body),
+        // memories of json export are restored into their own decks
+        ImportFormat::Json if query.deck_id.is_some() => {
+            return Ok(HttpResponse::BadRequest().body("'deck_id' can't be used with json import"));
+        }
+        ImportFormat::Json => {
+            return restore_memories(pool, phase_sets.current(), user_id, &body).await
+        }
+    };
+    let rows = match rows {
+        Err(err) => return Ok(HttpResponse::BadRequest().body(err.to_string())),
+        Ok(rows) => rows,
+    };
+
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let deck_id = query.deck_id;
+    let response = web::block(move || {
+        let mut errors = Vec::new();
+        let mut lines = Vec::new();
+        let mut new_memories = Vec::new();
+        for row in rows.iter() {
+            match &row.memory {
+                Err(err) => errors.push(ImportRowError {
+                    row: row.line,
+                    error: err.to_string(),
+                }),
+                Ok(memory) => {
+                    lines.push(row.line);
+                    let new_memory = models::NewMemory {
+                        user_id,
+                        topic: memory.topic.as_deref(),
+                        text: &memory.text,
+                        algorithm: None,
+                        deck_id,
+                        format: memory.format.as_str(),
+                        back: memory.back.as_deref(),
+                    };
+                    new_memories.push((new_memory, memory.tags.as_slice()));
+                }
+            }
+        }
+
+        let saved = db_actions::import_memories(
+            user_id,
+            deck_id,
+            &new_memories,
+            &phase_sets.current(),
+            &conn,
+        )?;
+        Ok(import_response(lines.into_iter().zip(saved), errors))
+    })
+    .await
+    .map_err(super::memories::memory_error)?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+async fn restore_memories(
+    pool: web::Data<DbPool>,
+    phase_sets: Arc<PhaseSets>,
+    user_id: i32,
+    body: &str,
+) -> Result<HttpResponse, Error> {
+    let export = match serde_json::from_str::<Export>(body) {
+        Err(err) => return Ok(HttpResponse::BadRequest().body(format!("invalid export: {}", err))),
+        Ok(export) => export,
+    };
+
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let response = web::block(move || {
+        let restored = db_actions::restore_memories(user_id, &export, &phase_sets, &conn)?;
+        Ok(import_response((1..).zip(restored), Vec::new()))
+    })
+    .await
+    .map_err(super::memories::memory_error)?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Ids of saved memories and errors of the rest ordered by row.
+fn import_response<I>(saved: I, mut errors: Vec<ImportRowError>) -> ImportResponse
+where
+    I: Iterator<Item = (u64, Result<i32, failure::Error>)>,
+{
+    let mut memory_ids = Vec::new();
+    for (row, result) in saved {
+        match result {
+            Ok(memory_id) => memory_ids.push(memory_id),
+            Err(err) => {
+                error!("fail to import row {}, reason: '{}'", row, err);
+                errors.push(ImportRowError {
+                    row,
+                    error: err.to_string(),
+                });
+            }
+        }
+    }
+    errors.sort_by_key(|e| e.row);
+
+    ImportResponse { memory_ids, errors }
+}
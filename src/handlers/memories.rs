@@ -0,0 +1,383 @@
+use crate::auth::{self, AuthUser};
+use crate::data::*;
+use crate::db_actions::{self, ScheduleLeased, UnknownDeck};
+use crate::markdown::TextFormat;
+use crate::models::{self, MemoryWithSchedules, ScheduleAction};
+use crate::phase::ReloadablePhaseSets;
+use crate::DbPool;
+use actix_web::error::BlockingError;
+use actix_web::{delete, get, patch, post, web, Error, HttpResponse};
+use diesel::pg::PgConnection;
+use log::error;
+use std::sync::Arc;
+
+#[post("/memories/{memory_id}/review")]
+pub async fn review_memory(
+    pool: web::Data<DbPool>,
+    phase_sets: web::Data<Arc<ReloadablePhaseSets>>,
+    user: AuthUser,
+    memory_id_param: web::Path<i32>,
+    request: web::Json<ReviewRequest>,
+) -> Result<HttpResponse, Error> {
+    let memory_id = memory_id_param.into_inner();
+    check_memory(&pool, &user, memory_id).await?;
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let card = request.card;
+    let schedule = web::block(move || {
+        db_actions::review_memory(memory_id, card, request.grade, &phase_sets.current(), &conn)
+    })
+    .await
+    .map_err(memory_error)?;
+
+    let result = match (schedule, card) {
+        (None, Some(card)) => HttpResponse::NotFound().body(format!(
+            "No card {} found in memory with id '{}'",
+            card, memory_id
+        )),
+        (None, None) => {
+            HttpResponse::NotFound().body(format!("No memory found with id '{}'", memory_id))
+        }
+        (Some(s), _) => HttpResponse::Ok().json(ReviewResponse {
+            memory_id,
+            card: s.card,
+            phase_number: s.phase_number,
+            next_run: s.next_run,
+        }),
+    };
+
+    Ok(result)
+}
+
+#[get("/memories/{memory_id}/history")]
+pub async fn get_history(
+    pool: web::Data<DbPool>,
+    user: AuthUser,
+    memory_id_param: web::Path<i32>,
+) -> Result<HttpResponse, Error> {
+    let memory_id = memory_id_param.into_inner();
+    check_memory(&pool, &user, memory_id).await?;
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let history = web::block(move || db_actions::get_history(memory_id, &conn))
+        .await
+        .map_err(|e| {
+            error!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        })?;
+
+    let result = match history {
+        None => HttpResponse::NotFound().body(format!("No memory found with id '{}'", memory_id)),
+        Some(reviews) => HttpResponse::Ok().json(reviews),
+    };
+
+    Ok(result)
+}
+
+#[get("/users/{user_id}/memories")]
+pub async fn get_user_memories(
+    pool: web::Data<DbPool>,
+    user: AuthUser,
+    user_id_param: web::Path<i32>,
+    query: web::Query<PageQuery>,
+    filter: web::Query<MemoryFilter>,
+) -> Result<HttpResponse, Error> {
+    let user_id = user_id_param.into_inner();
+    auth::check_owner(&user.0, user_id)?;
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let (page, per_page) = query.bounds();
+    let memories = web::block(move || {
+        let found = db_actions::get_user_memories(
+            user_id,
+            filter.deck_id,
+            filter.tag.as_deref(),
+            (page - 1) * per_page,
+            per_page,
+            &conn,
+        )?;
+        match found {
+            None => Ok(None),
+            Some((memories, total)) => Ok(Some((with_tags(memories, &conn)?, total))),
+        }
+    })
+    .await
+    .map_err(|e: BlockingError<diesel::result::Error>| {
+        error!("{}", e);
+        HttpResponse::InternalServerError().finish()
+    })?;
+
+    let result = match memories {
+        None => HttpResponse::NotFound().body(format!("No user found with id '{}'", user_id)),
+        Some((memories, total)) => HttpResponse::Ok().json(MemoriesPage {
+            memories,
+            page,
+            per_page,
+            total,
+        }),
+    };
+
+    Ok(result)
+}
+
+#[get("/memories/{memory_id}")]
+pub async fn get_memory(
+    pool: web::Data<DbPool>,
+    user: AuthUser,
+    memory_id_param: web::Path<i32>,
+) -> Result<HttpResponse, Error> {
+    let memory_id = memory_id_param.into_inner();
+    check_memory(&pool, &user, memory_id).await?;
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let memory =
+        web::block(move || tagged_memory(db_actions::get_memory(memory_id, &conn)?, &conn))
+            .await
+            .map_err(|e| {
+                error!("{}", e);
+                HttpResponse::InternalServerError().finish()
+            })?;
+
+    Ok(memory_response(memory_id, memory))
+}
+
+#[patch("/memories/{memory_id}")]
+pub async fn update_memory(
+    pool: web::Data<DbPool>,
+    phase_sets: web::Data<Arc<ReloadablePhaseSets>>,
+    user: AuthUser,
+    memory_id_param: web::Path<i32>,
+    request: web::Json<UpdateMemoryRequest>,
+) -> Result<HttpResponse, Error> {
+    let memory_id = memory_id_param.into_inner();
+    check_memory(&pool, &user, memory_id).await?;
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let memory = web::block(move || {
+        let changes = models::MemoryChanges {
+            topic: request.topic.as_deref(),
+            text: request.text.as_deref(),
+            deck_id: request.deck_id,
+            format: request.format.map(TextFormat::as_str),
+            back: request.back.as_ref().map(|b| b.as_deref()),
+        };
+        let memory = db_actions::update_memory(
+            memory_id,
+            &changes,
+            request.tags.as_deref(),
+            &phase_sets.current(),
+            &conn,
+        )?;
+        Ok(tagged_memory(memory, &conn)?)
+    })
+    .await
+    .map_err(memory_error)?;
+
+    Ok(memory_response(memory_id, memory))
+}
+
+#[delete("/memories/{memory_id}")]
+pub async fn delete_memory(
+    pool: web::Data<DbPool>,
+    user: AuthUser,
+    memory_id_param: web::Path<i32>,
+) -> Result<HttpResponse, Error> {
+    let memory_id = memory_id_param.into_inner();
+    check_memory(&pool, &user, memory_id).await?;
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let deleted = web::block(move || db_actions::delete_memory(memory_id, &conn))
+        .await
+        .map_err(|e| {
+            error!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        })?;
+
+    let result = if deleted {
+        HttpResponse::NoContent().finish()
+    } else {
+        HttpResponse::NotFound().body(format!("No memory found with id '{}'", memory_id))
+    };
+
+    Ok(result)
+}
+
+#[post("/memories/{memory_id}/pause")]
+pub async fn pause_memory(
+    pool: web::Data<DbPool>,
+    phase_sets: web::Data<Arc<ReloadablePhaseSets>>,
+    user: AuthUser,
+    memory_id_param: web::Path<i32>,
+) -> Result<HttpResponse, Error> {
+    change_schedule(
+        pool,
+        phase_sets,
+        user,
+        memory_id_param.into_inner(),
+        ScheduleAction::Pause,
+    )
+    .await
+}
+
+#[post("/memories/{memory_id}/resume")]
+pub async fn resume_memory(
+    pool: web::Data<DbPool>,
+    phase_sets: web::Data<Arc<ReloadablePhaseSets>>,
+    user: AuthUser,
+    memory_id_param: web::Path<i32>,
+) -> Result<HttpResponse, Error> {
+    change_schedule(
+        pool,
+        phase_sets,
+        user,
+        memory_id_param.into_inner(),
+        ScheduleAction::Resume,
+    )
+    .await
+}
+
+#[post("/memories/{memory_id}/reset")]
+pub async fn reset_memory(
+    pool: web::Data<DbPool>,
+    phase_sets: web::Data<Arc<ReloadablePhaseSets>>,
+    user: AuthUser,
+    memory_id_param: web::Path<i32>,
+) -> Result<HttpResponse, Error> {
+    change_schedule(
+        pool,
+        phase_sets,
+        user,
+        memory_id_param.into_inner(),
+        ScheduleAction::Reset,
+    )
+    .await
+}
+
+async fn change_schedule(
+    pool: web::Data<DbPool>,
+    phase_sets: web::Data<Arc<ReloadablePhaseSets>>,
+    user: AuthUser,
+    memory_id: i32,
+    action: ScheduleAction,
+) -> Result<HttpResponse, Error> {
+    check_memory(&pool, &user, memory_id).await?;
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let memory = web::block(move || {
+        db_actions::change_schedule(memory_id, action, &phase_sets.current(), &conn)?;
+        tagged_memory(db_actions::get_memory(memory_id, &conn)?, &conn)
+            .map_err(failure::Error::from)
+    })
+    .await
+    .map_err(|e| {
+        error!("{}", e);
+        HttpResponse::InternalServerError().finish()
+    })?;
+
+    Ok(memory_response(memory_id, memory))
+}
+
+#[post("/users/{user_id}/tags/{tag}/pause")]
+pub async fn pause_tagged(
+    pool: web::Data<DbPool>,
+    phase_sets: web::Data<Arc<ReloadablePhaseSets>>,
+    user: AuthUser,
+    path: web::Path<(i32, String)>,
+) -> Result<HttpResponse, Error> {
+    let (user_id, tag) = path.into_inner();
+    auth::check_owner(&user.0, user_id)?;
+    change_tagged(pool, phase_sets, user_id, tag, ScheduleAction::Pause).await
+}
+
+#[post("/users/{user_id}/tags/{tag}/resume")]
+pub async fn resume_tagged(
+    pool: web::Data<DbPool>,
+    phase_sets: web::Data<Arc<ReloadablePhaseSets>>,
+    user: AuthUser,
+    path: web::Path<(i32, String)>,
+) -> Result<HttpResponse, Error> {
+    let (user_id, tag) = path.into_inner();
+    auth::check_owner(&user.0, user_id)?;
+    change_tagged(pool, phase_sets, user_id, tag, ScheduleAction::Resume).await
+}
+
+async fn change_tagged(
+    pool: web::Data<DbPool>,
+    phase_sets: web::Data<Arc<ReloadablePhaseSets>>,
+    user_id: i32,
+    tag: String,
+    action: ScheduleAction,
+) -> Result<HttpResponse, Error> {
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let changed = web::block(move || {
+        db_actions::change_tagged_schedules(user_id, &tag, action, &phase_sets.current(), &conn)
+    })
+    .await
+    .map_err(|e| {
+        error!("{}", e);
+        HttpResponse::InternalServerError().finish()
+    })?;
+
+    Ok(HttpResponse::Ok().json(TaggedChangeResponse { changed }))
+}
+
+/// Fails with 404 when there is no such memory and with 403 when it belongs to another user.
+async fn check_memory(
+    pool: &web::Data<DbPool>,
+    user: &AuthUser,
+    memory_id: i32,
+) -> Result<(), HttpResponse> {
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let owner = web::block(move || db_actions::get_memory_owner(memory_id, &conn))
+        .await
+        .map_err(|e| {
+            error!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        })?;
+
+    match owner {
+        None => {
+            Err(HttpResponse::NotFound().body(format!("No memory found with id '{}'", memory_id)))
+        }
+        Some(owner_id) => auth::check_owner(&user.0, owner_id),
+    }
+}
+
+/// Deck of another user is reported as bad request, card which reminder is being delivered as conflict.
+pub fn memory_error(err: BlockingError<failure::Error>) -> HttpResponse {
+    match err {
+        BlockingError::Error(err) if err.downcast_ref::<UnknownDeck>().is_some() => {
+            HttpResponse::BadRequest().body(err.to_string())
+        }
+        BlockingError::Error(err) if err.downcast_ref::<ScheduleLeased>().is_some() => {
+            HttpResponse::Conflict().body(err.to_string())
+        }
+        err => {
+            error!("{}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+fn with_tags(
+    memories: Vec<MemoryWithSchedules>,
+    conn: &PgConnection,
+) -> Result<Vec<MemoryResponse>, diesel::result::Error> {
+    let ids: Vec<i32> = memories.iter().map(|m| m.memory.id).collect();
+    let mut tags = db_actions::get_memory_tags(&ids, conn)?;
+
+    Ok(memories
+        .into_iter()
+        .map(|m| {
+            let memory_tags = tags.remove(&m.memory.id).unwrap_or_default();
+            MemoryResponse::new(m, memory_tags)
+        })
+        .collect())
+}
+
+fn tagged_memory(
+    memory: Option<MemoryWithSchedules>,
+    conn: &PgConnection,
+) -> Result<Option<MemoryResponse>, diesel::result::Error> {
+    Ok(with_tags(memory.into_iter().collect(), conn)?.pop())
+}
+
+fn memory_response(memory_id: i32, memory: Option<MemoryResponse>) -> HttpResponse {
+    match memory {
+        None => HttpResponse::NotFound().body(format!("No memory found with id '{}'", memory_id)),
+        Some(m) => HttpResponse::Ok().json(m),
+    }
+}
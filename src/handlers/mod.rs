@@ -1,3 +1,13 @@
+pub mod admin;
+pub mod decks;
+pub mod export;
+pub mod grade;
+pub mod import;
+pub mod memories;
+pub mod unsubscribe;
+pub mod users;
+pub mod verification;
+
 use actix_web::{Error, HttpRequest, HttpResponse};
 use std::thread;
 use std::time::Duration;
@@ -36,3 +46,21 @@ pub fn json_error_handler(err: actix_web::error::JsonPayloadError, _: &HttpReque
     )
     .into()
 }
+
+/// Answer to a signed link from email which signature doesn't match.
+pub fn invalid_link() -> HttpResponse {
+    HttpResponse::Forbidden().body("Link is invalid")
+}
+
+/// Page with a button that posts to the same link.
+/// Links from emails act only on POST, because mail scanners follow links by GET.
+pub fn confirmation_page(question: &str, action: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(format!(
+            "<!DOCTYPE html>\n<html><body>\n<p>{}</p>\n\
+         <form method=\"post\"><button type=\"submit\">{}</button></form>\n\
+         </body></html>\n",
+            question, action
+        ))
+}
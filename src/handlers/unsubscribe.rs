@@ -0,0 +1,100 @@
+use super::{confirmation_page, invalid_link};
+use crate::db_actions;
+use crate::links::Links;
+use crate::models::ScheduleAction;
+use crate::phase::ReloadablePhaseSets;
+use crate::DbPool;
+use actix_web::{get, post, web, Error, HttpResponse};
+use log::error;
+use std::sync::Arc;
+
+// Mail clients send POST to the link from `List-Unsubscribe` header, it acts right away (RFC 8058).
+
+#[get("/unsubscribe/users/{user_id}/{signature}")]
+pub async fn confirm_unsubscribe_user(
+    links: web::Data<Arc<Links>>,
+    path: web::Path<(i32, String)>,
+) -> HttpResponse {
+    let (user_id, signature) = path.into_inner();
+    if !links.check_user(user_id, &signature) {
+        return invalid_link();
+    }
+
+    confirmation_page("Stop all reminders?", "Unsubscribe")
+}
+
+#[post("/unsubscribe/users/{user_id}/{signature}")]
+pub async fn unsubscribe_user(
+    pool: web::Data<DbPool>,
+    links: web::Data<Arc<Links>>,
+    path: web::Path<(i32, String)>,
+) -> Result<HttpResponse, Error> {
+    let (user_id, signature) = path.into_inner();
+    if !links.check_user(user_id, &signature) {
+        return Ok(invalid_link());
+    }
+
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let unsubscribed = web::block(move || db_actions::unsubscribe_user(user_id, &conn))
+        .await
+        .map_err(|e| {
+            error!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        })?;
+
+    let result = if unsubscribed {
+        HttpResponse::Ok().body("You will not receive reminders anymore")
+    } else {
+        HttpResponse::NotFound().body(format!("No user found with id '{}'", user_id))
+    };
+
+    Ok(result)
+}
+
+#[get("/unsubscribe/memories/{memory_id}/{signature}")]
+pub async fn confirm_pause_memory(
+    links: web::Data<Arc<Links>>,
+    path: web::Path<(i32, String)>,
+) -> HttpResponse {
+    let (memory_id, signature) = path.into_inner();
+    if !links.check_memory(memory_id, &signature) {
+        return invalid_link();
+    }
+
+    confirmation_page("Stop reminders about this memory?", "Pause")
+}
+
+#[post("/unsubscribe/memories/{memory_id}/{signature}")]
+pub async fn pause_memory(
+    pool: web::Data<DbPool>,
+    phase_sets: web::Data<Arc<ReloadablePhaseSets>>,
+    links: web::Data<Arc<Links>>,
+    path: web::Path<(i32, String)>,
+) -> Result<HttpResponse, Error> {
+    let (memory_id, signature) = path.into_inner();
+    if !links.check_memory(memory_id, &signature) {
+        return Ok(invalid_link());
+    }
+
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let paused = web::block(move || {
+        db_actions::change_schedule(
+            memory_id,
+            ScheduleAction::Pause,
+            &phase_sets.current(),
+            &conn,
+        )
+    })
+    .await
+    .map_err(|e| {
+        error!("{}", e);
+        HttpResponse::InternalServerError().finish()
+    })?;
+
+    let result = match paused {
+        None => HttpResponse::NotFound().body(format!("No memory found with id '{}'", memory_id)),
+        Some(_) => HttpResponse::Ok().body("You will not receive reminders about this memory"),
+    };
+
+    Ok(result)
+}
@@ -0,0 +1,60 @@
+use crate::auth::{self, AuthUser};
+use crate::data::*;
+use crate::db_actions;
+use crate::delivery::DeliveryMode;
+use crate::models;
+use crate::phase::ReloadablePhaseSets;
+use crate::quiet_hours;
+use crate::DbPool;
+use actix_web::{patch, web, Error, HttpResponse};
+use log::error;
+use std::sync::Arc;
+
+#[patch("/users/{user_id}")]
+pub async fn update_user(
+    pool: web::Data<DbPool>,
+    phase_sets: web::Data<Arc<ReloadablePhaseSets>>,
+    user: AuthUser,
+    user_id_param: web::Path<i32>,
+    request: web::Json<UpdateUserRequest>,
+) -> Result<HttpResponse, Error> {
+    auth::check_owner(&user.0, *user_id_param)?;
+    if let Some(timezone) = &request.timezone {
+        if let Err(err) = quiet_hours::parse_timezone(timezone) {
+            return Ok(HttpResponse::BadRequest().body(err.to_string()));
+        }
+    }
+    if let Some(Some(phase_set)) = &request.phase_set {
+        if let Err(err) = phase_sets.current().check(phase_set) {
+            return Ok(HttpResponse::BadRequest().body(err.to_string()));
+        }
+    }
+
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let user_id = user_id_param.into_inner();
+    let user = web::block(move || {
+        let quiet_hours = request.quiet_hours.as_ref();
+        let changes = models::UserChanges {
+            timezone: request.timezone.as_deref(),
+            quiet_start: quiet_hours.map(|q| q.as_ref().map(|q| q.start)),
+            quiet_end: quiet_hours.map(|q| q.as_ref().map(|q| q.end)),
+            delivery_mode: request.delivery_mode.map(DeliveryMode::as_str),
+            digest_time: request.digest_time,
+            phase_set: request.phase_set.as_ref().map(|p| p.as_deref()),
+            unsubscribed: request.unsubscribed,
+        };
+        db_actions::update_user(user_id, &changes, &conn)
+    })
+    .await
+    .map_err(|e| {
+        error!("{}", e);
+        HttpResponse::InternalServerError().finish()
+    })?;
+
+    let result = match user {
+        None => HttpResponse::NotFound().body(format!("No user found with id '{}'", user_id)),
+        Some(u) => HttpResponse::Ok().json(u),
+    };
+
+    Ok(result)
+}
@@ -0,0 +1,105 @@
+use crate::auth::{self, AuthUser};
+use crate::db_actions;
+use crate::links::Links;
+use crate::models;
+use crate::notifier::{Notification, Notifier};
+use crate::DbPool;
+use actix_web::{get, post, web, Error, HttpResponse};
+use diesel::pg::PgConnection;
+use log::error;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// How long a mailed verification link works, a new one is requested with `POST /verification`.
+const TOKEN_TTL_SECS: i64 = 24 * 60 * 60;
+
+/// Issues a new verification token to user and mails the link with it, previous links stop working.
+pub fn send_token(
+    notifier: &dyn Notifier,
+    links: &Links,
+    user_id: i32,
+    email: &str,
+    conn: &PgConnection,
+) -> Result<(), failure::Error> {
+    let (token, token_hash) = auth::new_secret();
+    let new_token = models::NewVerificationToken {
+        user_id,
+        token_hash: &token_hash,
+    };
+    db_actions::replace_verification_token(&new_token, conn)?;
+
+    let text = format!(
+        "Open the link to confirm your email and start receiving reminders:\n{}\n\n\
+         If you didn't sign up, just ignore this message.",
+        links.verify(&token)
+    );
+    notifier.notify(&Notification {
+        address: email,
+        topic: Some("Confirm your email"),
+        text: &text,
+        html: None,
+        unsubscribe: None,
+    })
+}
+
+#[get("/verify/{token}")]
+pub async fn verify_email(
+    pool: web::Data<DbPool>,
+    token_param: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let token_hash = auth::hash_secret(&token_param.into_inner());
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_err(|_| HttpResponse::InternalServerError().finish())?
+        .as_secs() as i64;
+    let user =
+        web::block(move || db_actions::verify_user(&token_hash, now - TOKEN_TTL_SECS, &conn))
+            .await
+            .map_err(|e| {
+                error!("{}", e);
+                HttpResponse::InternalServerError().finish()
+            })?;
+
+    let result = match user {
+        None => HttpResponse::NotFound()
+            .body("Verification link is invalid, expired or was already used"),
+        Some(u) => HttpResponse::Ok().body(format!(
+            "Email '{}' is confirmed, reminders will be sent to it",
+            u.email
+        )),
+    };
+
+    Ok(result)
+}
+
+/// Mails a new verification link, e.g. when the first one was lost.
+#[post("/verification")]
+pub async fn resend_verification(
+    pool: web::Data<DbPool>,
+    notifier: web::Data<Arc<dyn Notifier>>,
+    links: web::Data<Arc<Links>>,
+    user: AuthUser,
+) -> Result<HttpResponse, Error> {
+    if user.0.verified {
+        return Ok(HttpResponse::Conflict().body("Email is already confirmed"));
+    }
+
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    web::block(move || {
+        send_token(
+            notifier.as_ref().as_ref(),
+            &links,
+            user.0.id,
+            &user.0.email,
+            &conn,
+        )
+    })
+    .await
+    .map_err(|e| {
+        error!("fail to send verification link, reason: '{}'", e);
+        HttpResponse::InternalServerError().finish()
+    })?;
+
+    Ok(HttpResponse::Accepted().finish())
+}
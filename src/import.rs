@@ -0,0 +1,296 @@
+use crate::markdown::TextFormat;
+use csv::{ReaderBuilder, StringRecord};
+
+/// Prefix of Anki header lines, e.g. `#separator:tab`.
+const ANKI_HEADER: &str = "#";
+/// Anki header that tells which column holds something other than note fields, e.g. `#tags column:3`.
+const ANKI_COLUMN: &str = " column:";
+
+#[derive(Debug, Fail)]
+pub enum ImportError {
+    #[fail(display = "CSV header has no 'text' or 'front' column")]
+    NoTextColumn,
+    #[fail(display = "unknown separator '{}'", name)]
+    UnknownSeparator { name: String },
+    #[fail(display = "fail to read header: {}", reason)]
+    Unreadable { reason: String },
+}
+
+#[derive(Debug, Fail, PartialEq)]
+pub enum RowError {
+    #[fail(display = "fail to read row: {}", reason)]
+    Unreadable { reason: String },
+    #[fail(display = "text is empty")]
+    EmptyText,
+}
+
+/// Format of imported file.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    /// Comma separated values with a header, e.g. `topic,text` or `front,back,tags`.
+    Csv,
+    /// Notes exported by Anki as plain text, tab separated by default.
+    Anki,
+    /// Export of this service, restored with schedules and history as they were.
+    Json,
+}
+
+/// Memory read from one row of imported file.
+#[derive(Debug, PartialEq)]
+pub struct ImportedMemory {
+    pub topic: Option<String>,
+    pub text: String,
+    pub back: Option<String>,
+    pub tags: Vec<String>,
+    pub format: TextFormat,
+}
+
+/// Row of imported file starting at 1-based `line`.
+#[derive(Debug)]
+pub struct Row {
+    pub line: u64,
+    pub memory: Result<ImportedMemory, RowError>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Column {
+    Topic,
+    Text,
+    Back,
+    /// Tags separated by spaces.
+    Tags,
+}
+
+/// Reads memories from CSV `body`, a broken row is reported in its place without stopping the rest.
+pub fn parse_csv(body: &str) -> Result<Vec<Row>, ImportError> {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(body.as_bytes());
+    let mut records = reader.records();
+    let header = match records.next() {
+        None => return Ok(Vec::new()),
+        Some(header) => header.map_err(|err| ImportError::Unreadable {
+            reason: err.to_string(),
+        })?,
+    };
+
+    let columns: Vec<Option<Column>> = header
+        .iter()
+        .map(|name| match name.trim().to_lowercase().as_str() {
+            "topic" => Some(Column::Topic),
+            "text" | "front" => Some(Column::Text),
+            "back" => Some(Column::Back),
+            "tags" => Some(Column::Tags),
+            _ => None,
+        })
+        .collect();
+    if !columns.contains(&Some(Column::Text)) {
+        return Err(ImportError::NoTextColumn);
+    }
+
+    Ok(read_rows(records, &columns, 0, TextFormat::Plain))
+}
+
+/// Anki puts optional header lines before notes: `#separator:...`, `#html:true` and `#<what> column:<number>`
+/// for columns that are not note fields. The first two note fields are front and back,
+/// exports without `#tags column` header are read as `front<tab>back<tab>tags`.
+pub fn parse_anki(body: &str) -> Result<Vec<Row>, ImportError> {
+    let mut separator = b'\t';
+    let mut format = TextFormat::Plain;
+    // 1-based column numbers of non field columns
+    let mut reserved: Vec<(usize, Option<Column>)> = Vec::new();
+    let mut header_lines = 0;
+    let mut notes = body;
+    while notes.starts_with(ANKI_HEADER) {
+        let (line, rest) = match notes.find('\n') {
+            Some(end) => (&notes[..end], &notes[end + 1..]),
+            None => (notes, ""),
+        };
+        let header = line[ANKI_HEADER.len()..].trim();
+        if let Some(name) = header.strip_prefix("separator:") {
+            separator = anki_separator(name)?;
+        } else if let Some(html) = header.strip_prefix("html:") {
+            if html == "true" {
+                // markdown keeps inline HTML and sanitizes it
+                format = TextFormat::Markdown;
+            }
+        } else if let Some(at) = header.find(ANKI_COLUMN) {
+            if let Ok(number) = header[at + ANKI_COLUMN.len()..].parse::<usize>() {
+                let column = Some(Column::Tags).filter(|_| &header[..at] == "tags");
+                reserved.push((number, column));
+            }
+        }
+        header_lines += 1;
+        notes = rest;
+    }
+
+    let mut fields = if reserved.iter().any(|(_, c)| *c == Some(Column::Tags)) {
+        vec![Column::Text, Column::Back]
+    } else {
+        vec![Column::Text, Column::Back, Column::Tags]
+    }
+    .into_iter();
+    let last_reserved = reserved.iter().map(|(n, _)| *n).max().unwrap_or(0);
+    let mut columns = Vec::new();
+    for number in 1..=last_reserved + fields.len() {
+        match reserved.iter().find(|(n, _)| *n == number) {
+            Some((_, column)) => columns.push(*column),
+            None => columns.push(fields.next()),
+        }
+    }
+
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(separator)
+        .from_reader(notes.as_bytes());
+    Ok(read_rows(reader.records(), &columns, header_lines, format))
+}
+
+fn anki_separator(name: &str) -> Result<u8, ImportError> {
+    match name.trim().to_lowercase().as_str() {
+        "tab" | "\t" => Ok(b'\t'),
+        "comma" | "," => Ok(b','),
+        "semicolon" | ";" => Ok(b';'),
+        "colon" | ":" => Ok(b':'),
+        "pipe" | "|" => Ok(b'|'),
+        "space" | "" => Ok(b' '),
+        _ => Err(ImportError::UnknownSeparator {
+            name: name.to_string(),
+        }),
+    }
+}
+
+fn read_rows<I>(
+    records: I,
+    columns: &[Option<Column>],
+    line_offset: u64,
+    format: TextFormat,
+) -> Vec<Row>
+where
+    I: Iterator<Item = Result<StringRecord, csv::Error>>,
+{
+    records
+        .map(|record| match record {
+            Ok(record) => Row {
+                line: line_offset + record.position().map_or(0, |p| p.line()),
+                memory: read_memory(&record, columns, format),
+            },
+            Err(err) => Row {
+                line: line_offset + err.position().map_or(0, |p| p.line()),
+                memory: Err(RowError::Unreadable {
+                    reason: err.to_string(),
+                }),
+            },
+        })
+        .collect()
+}
+
+fn read_memory(
+    record: &StringRecord,
+    columns: &[Option<Column>],
+    format: TextFormat,
+) -> Result<ImportedMemory, RowError> {
+    let mut memory = ImportedMemory {
+        topic: None,
+        text: String::new(),
+        back: None,
+        tags: Vec::new(),
+        format,
+    };
+    for (field, column) in record.iter().map(str::trim).zip(columns) {
+        let value = Some(field.to_string()).filter(|f| !f.is_empty());
+        match column {
+            Some(Column::Topic) => memory.topic = value,
+            Some(Column::Text) => memory.text = field.to_string(),
+            Some(Column::Back) => memory.back = value,
+            Some(Column::Tags) => {
+                memory.tags = field.split_whitespace().map(String::from).collect()
+            }
+            None => {}
+        }
+    }
+
+    if memory.text.is_empty() {
+        Err(RowError::EmptyText)
+    } else {
+        Ok(memory)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memories(rows: Vec<Row>) -> Vec<(u64, Result<ImportedMemory, RowError>)> {
+        rows.into_iter().map(|r| (r.line, r.memory)).collect()
+    }
+
+    #[test]
+    fn csv_columns_are_found_by_header() {
+        let rows = parse_csv(
+            "Front,Back,Tags\n\"What is\n{{c1::ownership}}?\",,rust basics\n,no front,\nVec,growable array,\n",
+        )
+        .unwrap();
+        let rows = memories(rows);
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].0, 2);
+        let first = rows[0].1.as_ref().unwrap();
+        assert_eq!(first.text, "What is\n{{c1::ownership}}?");
+        assert_eq!(first.back, None);
+        assert_eq!(first.tags, vec!["rust", "basics"]);
+        assert_eq!(rows[1], (4, Err(RowError::EmptyText)));
+        assert_eq!(
+            rows[2].1.as_ref().unwrap().back.as_deref(),
+            Some("growable array")
+        );
+    }
+
+    #[test]
+    fn csv_needs_text_column() {
+        assert!(parse_csv("topic,text\nRust,Ownership\n").is_ok());
+        match parse_csv("topic,answer\nRust,Ownership\n") {
+            Err(ImportError::NoTextColumn) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn anki_headers_choose_separator_and_columns() {
+        let rows = parse_anki(
+            "#separator:tab\n#html:true\n#notetype column:1\n#tags column:4\nBasic\tWhat is <b>Box</b>?\tHeap pointer\trust\n",
+        )
+        .unwrap();
+        let rows = memories(rows);
+
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].0, 5);
+        assert_eq!(
+            rows[0].1,
+            Ok(ImportedMemory {
+                topic: None,
+                text: "What is <b>Box</b>?".to_string(),
+                back: Some("Heap pointer".to_string()),
+                tags: vec!["rust".to_string()],
+                format: TextFormat::Markdown,
+            })
+        );
+    }
+
+    #[test]
+    fn anki_without_headers_has_tags_after_fields() {
+        let rows = memories(parse_anki("Hola\tHello\tspanish greetings\n").unwrap());
+        let memory = rows[0].1.as_ref().unwrap();
+        assert_eq!(memory.text, "Hola");
+        assert_eq!(memory.tags, vec!["spanish", "greetings"]);
+        assert_eq!(memory.format, TextFormat::Plain);
+
+        match parse_anki("#separator:Hash\na#b\n") {
+            Err(ImportError::UnknownSeparator { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use crate::scheduling::Grade;
+use hmac::{Hmac, Mac, NewMac};
+use log::warn;
+use rand::RngCore;
+use sha2::Sha256;
+use std::env;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const USER: &str = "user";
+const MEMORY: &str = "memory";
+const GRADE: &str = "grade";
+const REVEAL: &str = "reveal";
+
+#[derive(Debug, Fail)]
+#[fail(display = "LINK_SECRET is a placeholder, set it to a random string or remove it")]
+pub struct PlaceholderSecret;
+
+/// Builds links to the service that are put into emails.
+/// Links that act without API key are signed, so nobody can change them to act on somebody else.
+pub struct Links {
+    base_url: String,
+    secret: Vec<u8>,
+}
+
+impl Links {
+    pub fn new(base_url: &str, secret: &[u8]) -> Links {
+        Links {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            secret: secret.to_vec(),
+        }
+    }
+
+    /// Base url is `PUBLIC_URL`, `http://localhost:8080` by default, links are signed with `LINK_SECRET`.
+    /// Fails when the secret is still a `{placeholder}`, anybody could sign links with it.
+    pub fn from_env() -> Result<Links, PlaceholderSecret> {
+        let base_url =
+            env::var("PUBLIC_URL").unwrap_or_else(|_| "http://localhost:8080".to_string());
+        let secret = match env::var("LINK_SECRET") {
+            Ok(secret) if secret.starts_with('{') && secret.ends_with('}') => {
+                return Err(PlaceholderSecret)
+            }
+            Ok(secret) if !secret.is_empty() => secret.into_bytes(),
+            _ => {
+                warn!("LINK_SECRET is not set, links in sent emails stop working after restart");
+                let mut secret = vec![0u8; 32];
+                rand::thread_rng().fill_bytes(&mut secret);
+                secret
+            }
+        };
+
+        Ok(Links::new(&base_url, &secret))
+    }
+
+    pub fn verify(&self, token: &str) -> String {
+        format!("{}/verify/{}", self.base_url, token)
+    }
+
+    /// Link that stops all reminders of user.
+    pub fn unsubscribe_user(&self, user_id: i32) -> String {
+        format!(
+            "{}/unsubscribe/users/{}/{}",
+            self.base_url,
+            user_id,
+            self.sign(&format!("{}:{}", USER, user_id))
+        )
+    }
+
+    /// Link that pauses reminders about one memory.
+    pub fn pause_memory(&self, memory_id: i32) -> String {
+        format!(
+            "{}/unsubscribe/memories/{}/{}",
+            self.base_url,
+            memory_id,
+            self.sign(&format!("{}:{}", MEMORY, memory_id))
+        )
+    }
+
+    /// Link to the page with the answer of a card and grade buttons, card is identified by its schedule.
+    pub fn reveal_card(&self, schedule_id: i32) -> String {
+        format!(
+            "{}/reveal/cards/{}/{}",
+            self.base_url,
+            schedule_id,
+            self.sign(&format!("{}:{}", REVEAL, schedule_id))
+        )
+    }
+
+    /// Link that records how well user recalled a card.
+    pub fn grade_card(&self, schedule_id: i32, grade: Grade) -> String {
+        format!(
+            "{}/grade/cards/{}/{}/{}",
+            self.base_url,
+            schedule_id,
+            grade.as_str(),
+            self.sign(&format!("{}:{}:{}", GRADE, schedule_id, grade.as_str()))
+        )
+    }
+
+    pub fn check_user(&self, user_id: i32, signature: &str) -> bool {
+        self.check(&format!("{}:{}", USER, user_id), signature)
+    }
+
+    pub fn check_memory(&self, memory_id: i32, signature: &str) -> bool {
+        self.check(&format!("{}:{}", MEMORY, memory_id), signature)
+    }
+
+    pub fn check_reveal(&self, schedule_id: i32, signature: &str) -> bool {
+        self.check(&format!("{}:{}", REVEAL, schedule_id), signature)
+    }
+
+    pub fn check_grade(&self, schedule_id: i32, grade: Grade, signature: &str) -> bool {
+        self.check(
+            &format!("{}:{}:{}", GRADE, schedule_id, grade.as_str()),
+            signature,
+        )
+    }
+
+    fn mac(&self, message: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_varkey(&self.secret).expect("hmac takes key of any size");
+        mac.update(message.as_bytes());
+        mac
+    }
+
+    fn sign(&self, message: &str) -> String {
+        hex::encode(self.mac(message).finalize().into_bytes())
+    }
+
+    fn check(&self, message: &str, signature: &str) -> bool {
+        match hex::decode(signature) {
+            Ok(tag) => self.mac(message).verify(&tag).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(link: &str) -> &str {
+        link.rsplit('/').next().unwrap()
+    }
+
+    #[test]
+    fn signed_links_act_only_on_their_target() {
+        let links = Links::new("https://memo.example/", b"secret");
+        let unsubscribe = links.unsubscribe_user(7);
+        assert!(unsubscribe.starts_with("https://memo.example/unsubscribe/users/7/"));
+        assert!(links.check_user(7, signature(&unsubscribe)));
+        assert!(!links.check_user(8, signature(&unsubscribe)));
+        assert!(!links.check_memory(7, signature(&unsubscribe)));
+        assert!(!links.check_reveal(7, signature(&unsubscribe)));
+        assert!(!links.check_user(7, "not-a-signature"));
+
+        let grade = links.grade_card(7, Grade::Good);
+        assert!(grade.starts_with("https://memo.example/grade/cards/7/good/"));
+        assert!(links.check_grade(7, Grade::Good, signature(&grade)));
+        assert!(!links.check_grade(7, Grade::Easy, signature(&grade)));
+
+        let other = Links::new("https://memo.example", b"other secret");
+        assert!(!other.check_user(7, signature(&unsubscribe)));
+    }
+}
@@ -15,21 +15,42 @@ use diesel::pg::PgConnection;
 use diesel::r2d2::{self, ConnectionManager};
 use dotenv::dotenv;
 use std::env;
+use std::sync::Arc;
 use std::time::Duration;
 
+mod auth;
+mod cards;
 mod data;
 mod db_actions;
+mod delivery;
+mod export;
 mod handlers;
+mod import;
+mod links;
+mod markdown;
 mod models;
+mod notifier;
 mod phase;
+mod quiet_hours;
 mod scheduler;
+mod scheduling;
 mod schema;
+mod templates;
+#[cfg(test)]
+mod test_db;
 
 use actix_rt;
 use actix_web::{get, middleware, post, App, Error, HttpServer};
 use actix_web::{web, HttpResponse};
+use auth::AuthUser;
 use data::*;
+use delivery::DeliveryMode;
+use links::Links;
 use log::{debug, error, info};
+use markdown::TextFormat;
+use notifier::Notifier;
+use phase::ReloadablePhaseSets;
+use scheduling::AlgorithmKind;
 
 pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
 
@@ -64,24 +85,89 @@ async fn main() -> Result<(), failure::Error> {
         .map_err(|_| StartError::NoDatbaseConnection)?;
 
     let conn = db_pool.get().map_err(|_| StartError::NoDatbaseConnection)?;
-    let phases = db_actions::get_phases(&conn)?;
+    let phase_sets = Arc::new(ReloadablePhaseSets::new(db_actions::get_phase_sets(&conn)?));
+
+    let notifier = notifier::from_env()?;
+    info!("Reminders will be delivered via '{}'", notifier.name());
+
+    let lease = scheduler::Lease::from_env()?;
+    info!("Scheduler works as '{}'", lease.owner);
+
+    scheduler::start_reloading_thread(
+        phase_sets.clone(),
+        Duration::from_secs(scheduler::var_or("PHASES_RELOAD_SECS", 5 * 60)?),
+        db_pool.clone(),
+    );
+    let links = Arc::new(links::Links::from_env()?);
+    let templates = Arc::new(templates::Templates::from_env()?);
 
-    scheduler::start_checking_thread(phases, Duration::from_secs(2), db_pool.clone());
+    let dispatcher = scheduler::Dispatcher {
+        notifier: notifier.clone(),
+        links: links.clone(),
+        templates: templates.clone(),
+        lease,
+        retry: scheduler::RetryPolicy::from_env()?,
+    };
+    scheduler::start_checking_thread(
+        phase_sets.clone(),
+        dispatcher,
+        Duration::from_secs(2),
+        db_pool.clone(),
+    );
 
     let bind_address = "0.0.0.0:8080";
     info!("Starting server on '{}'", bind_address);
     let bind_result = HttpServer::new(move || {
         App::new()
             .data(db_pool.clone())
+            .data(phase_sets.clone())
+            .data(notifier.clone())
+            .data(links.clone())
+            .data(templates.clone())
             .wrap(middleware::Logger::default())
             .app_data(
                 web::JsonConfig::default()
                     .limit(4096)
                     .error_handler(handlers::json_error_handler),
             )
+            .app_data(web::PayloadConfig::new(handlers::import::MAX_FILE_SIZE))
             .service(get_user)
             .service(create_user)
+            .service(handlers::users::update_user)
+            .service(handlers::verification::verify_email)
+            .service(handlers::verification::resend_verification)
+            .service(handlers::unsubscribe::confirm_unsubscribe_user)
+            .service(handlers::unsubscribe::unsubscribe_user)
+            .service(handlers::unsubscribe::confirm_pause_memory)
+            .service(handlers::unsubscribe::pause_memory)
+            .service(handlers::grade::confirm_grade)
+            .service(handlers::grade::grade_card)
+            .service(handlers::grade::reveal_card)
             .service(add_reminder)
+            .service(handlers::memories::review_memory)
+            .service(handlers::memories::get_history)
+            .service(handlers::memories::get_user_memories)
+            .service(handlers::memories::get_memory)
+            .service(handlers::memories::update_memory)
+            .service(handlers::memories::delete_memory)
+            .service(handlers::memories::pause_memory)
+            .service(handlers::memories::resume_memory)
+            .service(handlers::memories::reset_memory)
+            .service(handlers::memories::pause_tagged)
+            .service(handlers::memories::resume_tagged)
+            .service(handlers::import::import_memories)
+            .service(handlers::export::export_memories)
+            .service(handlers::decks::create_deck)
+            .service(handlers::decks::get_user_decks)
+            .service(handlers::decks::get_deck)
+            .service(handlers::decks::update_deck)
+            .service(handlers::decks::delete_deck)
+            .service(handlers::admin::get_failed_schedules)
+            .service(handlers::admin::requeue_schedule)
+            .service(handlers::admin::reload_phases)
+            .service(handlers::admin::get_phases)
+            .service(handlers::admin::replace_phases)
+            .service(handlers::admin::issue_api_key)
             .default_service(web::to(HttpResponse::NotFound))
     })
     .bind(bind_address)
@@ -98,38 +184,92 @@ async fn main() -> Result<(), failure::Error> {
 #[post("/create_user")]
 async fn create_user(
     pool: web::Data<DbPool>,
+    phase_sets: web::Data<Arc<ReloadablePhaseSets>>,
+    notifier: web::Data<Arc<dyn Notifier>>,
+    links: web::Data<Arc<Links>>,
     request: web::Json<CreateUserRequest>,
 ) -> Result<HttpResponse, Error> {
+    if let Some(timezone) = &request.timezone {
+        if let Err(err) = quiet_hours::parse_timezone(timezone) {
+            return Ok(HttpResponse::BadRequest().body(err.to_string()));
+        }
+    }
+    if let Some(phase_set) = &request.phase_set {
+        if let Err(err) = phase_sets.current().check(phase_set) {
+            return Ok(HttpResponse::BadRequest().body(err.to_string()));
+        }
+    }
+
     let conn = pool.get().expect("couldn't get db connection from pool");
-    let user_id = web::block(move || db_actions::insert_user(&request.email, &conn))
-        .await
-        .map_err(|e| {
-            error!("{}", e);
-            HttpResponse::InternalServerError().finish()
-        })?;
+    let (api_key, api_key_hash) = auth::new_secret();
+    let email = request.email.clone();
+    let user_id = web::block(move || {
+        let new_user = models::NewUser {
+            email: &request.email,
+            algorithm: request.algorithm.unwrap_or(AlgorithmKind::Fixed).as_str(),
+            timezone: request.timezone.as_deref().unwrap_or("UTC"),
+            quiet_start: request.quiet_hours.as_ref().map(|q| q.start),
+            quiet_end: request.quiet_hours.as_ref().map(|q| q.end),
+            delivery_mode: request
+                .delivery_mode
+                .unwrap_or(DeliveryMode::Immediate)
+                .as_str(),
+            digest_time: request.digest_time,
+            phase_set: request.phase_set.as_deref(),
+        };
+        db_actions::insert_user(&new_user, &api_key_hash, &conn)
+    })
+    .await
+    .map_err(|e| {
+        error!("{}", e);
+        HttpResponse::InternalServerError().finish()
+    })?;
+
+    // user is created anyway, the link may be requested again with the api key
+    let conn = pool.get().expect("couldn't get db connection from pool");
+    let sent = web::block(move || {
+        handlers::verification::send_token(
+            notifier.as_ref().as_ref(),
+            &links,
+            user_id,
+            &email,
+            &conn,
+        )
+    })
+    .await;
+    if let Err(err) = sent {
+        error!(
+            "fail to send verification link to user with id '{}', reason: '{}'",
+            user_id, err
+        );
+    }
 
-    Ok(HttpResponse::Ok().json(CreateUserResponse { user_id }))
+    Ok(HttpResponse::Ok().json(CreateUserResponse { user_id, api_key }))
 }
 
 #[post("/add_reminder")]
 async fn add_reminder(
     pool: web::Data<DbPool>,
+    phase_sets: web::Data<Arc<ReloadablePhaseSets>>,
+    user: AuthUser,
     request: web::Json<CreateMemoryRequest>,
 ) -> Result<HttpResponse, Error> {
+    auth::check_owner(&user.0, request.user_id)?;
     let conn = pool.get().expect("couldn't get db connection from pool");
     let memory_id = web::block(move || {
-        db_actions::insert_reminder(
-            request.user_id,
-            request.topic.as_deref(),
-            &request.text,
-            &conn,
-        )
+        let new_memory = models::NewMemory {
+            user_id: request.user_id,
+            topic: request.topic.as_deref(),
+            text: &request.text,
+            algorithm: request.algorithm.map(AlgorithmKind::as_str),
+            deck_id: request.deck_id,
+            format: request.format.unwrap_or(TextFormat::Plain).as_str(),
+            back: request.back.as_deref(),
+        };
+        db_actions::insert_reminder(&new_memory, &request.tags, &phase_sets.current(), &conn)
     })
     .await
-    .map_err(|e| {
-        error!("{}", e);
-        HttpResponse::InternalServerError().finish()
-    })?;
+    .map_err(handlers::memories::memory_error)?;
 
     Ok(HttpResponse::Ok().json(CreateMemoryResponse { memory_id }))
 }
@@ -137,10 +277,12 @@ async fn add_reminder(
 #[get("/user/{user_id}")]
 async fn get_user(
     pool: web::Data<DbPool>,
+    user: AuthUser,
     user_id_param: web::Path<i32>,
 ) -> Result<HttpResponse, Error> {
-    let conn = pool.get().expect("couldn't get db connection from pool");
     let user_id = user_id_param.into_inner();
+    auth::check_owner(&user.0, user_id)?;
+    let conn = pool.get().expect("couldn't get db connection from pool");
     let user = web::block(move || db_actions::get_user(user_id, &conn))
         .await
         .map_err(|e| {
@@ -172,6 +314,12 @@ mod tests {
             .uri("/create_user")
             .set_json(&CreateUserRequest {
                 email: "vasia@ya.ru".to_owned(),
+                algorithm: None,
+                timezone: None,
+                quiet_hours: None,
+                delivery_mode: None,
+                digest_time: None,
+                phase_set: None,
             })
             .to_request();
 
@@ -0,0 +1,187 @@
+use pulldown_cmark::{escape, html, CodeBlockKind, Event, Options, Parser, Tag};
+use std::str::FromStr;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::highlighted_html_for_string;
+use syntect::parsing::SyntaxSet;
+
+/// Inline styles that highlighted code may keep after sanitizing.
+const STYLE_PROPERTIES: [&str; 5] = [
+    "color",
+    "background-color",
+    "font-weight",
+    "font-style",
+    "text-decoration",
+];
+
+/// Light theme that reads well in mail clients.
+const CODE_THEME: &str = "InspiredGitHub";
+
+#[derive(Debug, Fail)]
+pub enum MarkdownError {
+    #[fail(display = "unknown text format '{}'", name)]
+    UnknownFormat { name: String },
+}
+
+/// How memory text is written.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TextFormat {
+    Plain,
+    Markdown,
+}
+
+impl TextFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TextFormat::Plain => "plain",
+            TextFormat::Markdown => "markdown",
+        }
+    }
+}
+
+impl FromStr for TextFormat {
+    type Err = MarkdownError;
+
+    fn from_str(name: &str) -> Result<TextFormat, MarkdownError> {
+        match name {
+            "plain" => Ok(TextFormat::Plain),
+            "markdown" => Ok(TextFormat::Markdown),
+            _ => Err(MarkdownError::UnknownFormat {
+                name: name.to_string(),
+            }),
+        }
+    }
+}
+
+/// Renders markdown to sanitized HTML with highlighted code blocks.
+pub struct Markdown {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+}
+
+impl Markdown {
+    pub fn new() -> Markdown {
+        let mut themes = ThemeSet::load_defaults();
+        Markdown {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme: themes
+                .themes
+                .remove(CODE_THEME)
+                .expect("default themes contain code theme"),
+        }
+    }
+
+    pub fn to_html(&self, text: &str) -> String {
+        let mut events = Vec::new();
+        // language and source of the code block being read
+        let mut code: Option<(String, String)> = None;
+        for event in Parser::new_ext(text, Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH) {
+            match (event, code.as_mut()) {
+                (Event::Start(Tag::CodeBlock(kind)), _) => {
+                    let language = match kind {
+                        CodeBlockKind::Fenced(info) => {
+                            info.split_whitespace().next().unwrap_or("").to_string()
+                        }
+                        CodeBlockKind::Indented => String::new(),
+                    };
+                    code = Some((language, String::new()));
+                }
+                (Event::Text(part), Some((_, source))) => source.push_str(&part),
+                (Event::End(Tag::CodeBlock(_)), Some((language, source))) => {
+                    events.push(Event::Html(self.highlight(language, source).into()));
+                    code = None;
+                }
+                (event, _) => events.push(event),
+            }
+        }
+
+        let mut unsafe_html = String::new();
+        html::push_html(&mut unsafe_html, events.into_iter());
+        sanitize(&unsafe_html)
+    }
+
+    fn highlight(&self, language: &str, source: &str) -> String {
+        let syntax = self
+            .syntaxes
+            .find_syntax_by_token(language)
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text());
+        highlighted_html_for_string(source, &self.syntaxes, syntax, &self.theme).unwrap_or_else(
+            |_| {
+                let mut escaped = String::new();
+                escape::escape_html(&mut escaped, source).expect("writing to string never fails");
+                format!("<pre>{}</pre>", escaped)
+            },
+        )
+    }
+}
+
+/// Removes scripts, event handlers and the like, keeps only simple inline styles of code.
+fn sanitize(html: &str) -> String {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .add_tag_attributes("span", &["style"])
+        .add_tag_attributes("pre", &["style"])
+        .attribute_filter(|_, attribute, value| {
+            if attribute == "style" {
+                Some(safe_style(value).into())
+            } else {
+                Some(value.into())
+            }
+        });
+    builder.clean(html).to_string()
+}
+
+fn safe_style(style: &str) -> String {
+    style
+        .split(';')
+        .filter_map(|declaration| {
+            let mut parts = declaration.splitn(2, ':');
+            let property = parts.next()?.trim();
+            let value = parts.next()?.trim();
+            let simple_value = value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '#' || c == '-' || c == ' ');
+            if STYLE_PROPERTIES.contains(&property) && simple_value {
+                Some(format!("{}:{};", property, value))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_markdown_without_scripts() {
+        let html = Markdown::new().to_html(
+            "# Title\n\n* one\n* **two**\n\n<script>alert(1)</script><a href=\"#\" onclick=\"x()\">link</a>",
+        );
+        assert!(html.contains("<h1>Title</h1>"));
+        assert!(html.contains("<li><strong>two</strong></li>"));
+        assert!(!html.contains("script"));
+        assert!(!html.contains("onclick"));
+    }
+
+    #[test]
+    fn code_blocks_are_highlighted() {
+        let html = Markdown::new().to_html("```rust\nfn main() {}\n```\n");
+        assert!(html.contains("<pre style=\""));
+        assert!(html.contains("<span style=\"color:#"));
+        assert!(html.contains("main"));
+    }
+
+    #[test]
+    fn only_simple_styles_are_kept() {
+        assert_eq!(
+            safe_style("color:#323232;background-color:#ffffff;"),
+            "color:#323232;background-color:#ffffff;"
+        );
+        assert_eq!(
+            safe_style("color:red;position:fixed;background-color:url(x)"),
+            "color:red;"
+        );
+    }
+}
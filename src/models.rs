@@ -1,17 +1,71 @@
+use crate::schema::api_keys;
+use crate::schema::decks;
 use crate::schema::memories;
+use crate::schema::memory_tags;
+use crate::schema::phases;
+use crate::schema::reviews;
 use crate::schema::schedules;
+use crate::schema::tags;
 use crate::schema::users;
+use crate::schema::verification_tokens;
+use chrono::NaiveTime;
 
-#[derive(Queryable, Serialize, Deserialize, Debug)]
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone)]
 pub struct User {
     pub id: i32,
     pub email: String,
+    pub algorithm: String,
+    pub timezone: String,
+    pub quiet_start: Option<NaiveTime>,
+    pub quiet_end: Option<NaiveTime>,
+    pub delivery_mode: String,
+    pub digest_time: Option<NaiveTime>,
+    pub phase_set: Option<String>,
+    /// Reminders are sent only after user confirms the email address.
+    pub verified: bool,
+    /// User opted out of all reminders.
+    pub unsubscribed: bool,
 }
 
 #[derive(Insertable)]
 #[table_name = "users"]
 pub struct NewUser<'a> {
     pub email: &'a str,
+    pub algorithm: &'a str,
+    pub timezone: &'a str,
+    pub quiet_start: Option<NaiveTime>,
+    pub quiet_end: Option<NaiveTime>,
+    pub delivery_mode: &'a str,
+    pub digest_time: Option<NaiveTime>,
+    pub phase_set: Option<&'a str>,
+}
+
+#[derive(AsChangeset)]
+#[table_name = "users"]
+pub struct UserChanges<'a> {
+    pub timezone: Option<&'a str>,
+    pub quiet_start: Option<Option<NaiveTime>>,
+    pub quiet_end: Option<Option<NaiveTime>>,
+    pub delivery_mode: Option<&'a str>,
+    pub digest_time: Option<Option<NaiveTime>>,
+    pub phase_set: Option<Option<&'a str>>,
+    pub unsubscribed: Option<bool>,
+}
+
+/// Only hash of a key is stored, the key itself is shown to user once.
+#[derive(Insertable)]
+#[table_name = "api_keys"]
+pub struct NewApiKey<'a> {
+    pub user_id: i32,
+    pub key_hash: &'a str,
+}
+
+/// Token mailed to user to confirm email address, only its hash is stored.
+#[derive(Insertable)]
+#[table_name = "verification_tokens"]
+pub struct NewVerificationToken<'a> {
+    pub user_id: i32,
+    pub token_hash: &'a str,
 }
 
 #[derive(Queryable, Serialize, Deserialize, Debug)]
@@ -20,6 +74,12 @@ pub struct Memory {
     pub user_id: i32,
     pub topic: Option<String>,
     pub text: String,
+    pub algorithm: Option<String>,
+    pub deck_id: Option<i32>,
+    /// How text is written, `plain` or `markdown`.
+    pub format: String,
+    /// Answer hidden until user reveals it, `text` is the question then.
+    pub back: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -28,6 +88,60 @@ pub struct NewMemory<'a> {
     pub user_id: i32,
     pub topic: Option<&'a str>,
     pub text: &'a str,
+    pub algorithm: Option<&'a str>,
+    pub deck_id: Option<i32>,
+    pub format: &'a str,
+    pub back: Option<&'a str>,
+}
+
+#[derive(AsChangeset)]
+#[table_name = "memories"]
+pub struct MemoryChanges<'a> {
+    pub topic: Option<&'a str>,
+    pub text: Option<&'a str>,
+    pub deck_id: Option<Option<i32>>,
+    pub format: Option<&'a str>,
+    pub back: Option<Option<&'a str>>,
+}
+
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone)]
+pub struct Deck {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub phase_set: Option<String>,
+    pub notifications_enabled: bool,
+}
+
+#[derive(Insertable)]
+#[table_name = "decks"]
+pub struct NewDeck<'a> {
+    pub user_id: i32,
+    pub name: &'a str,
+    pub phase_set: Option<&'a str>,
+    pub notifications_enabled: bool,
+}
+
+#[derive(AsChangeset)]
+#[table_name = "decks"]
+pub struct DeckChanges<'a> {
+    pub name: Option<&'a str>,
+    pub phase_set: Option<Option<&'a str>>,
+    pub notifications_enabled: Option<bool>,
+}
+
+#[derive(Insertable)]
+#[table_name = "tags"]
+pub struct NewTag<'a> {
+    pub user_id: i32,
+    pub name: &'a str,
+}
+
+#[derive(Insertable)]
+#[table_name = "memory_tags"]
+pub struct MemoryTag {
+    pub memory_id: i32,
+    pub tag_id: i32,
 }
 
 #[derive(Queryable, Serialize, Deserialize, Debug)]
@@ -35,6 +149,15 @@ pub struct Phase {
     pub id: i32,
     pub number: i32,
     pub seconds_to_wait: i64,
+    pub phase_set: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "phases"]
+pub struct NewPhase<'a> {
+    pub phase_number: i32,
+    pub seconds_to_wait: i64,
+    pub phase_set: &'a str,
 }
 
 #[derive(Insertable)]
@@ -43,6 +166,25 @@ pub struct NewSchedule {
     pub memory_id: i32,
     pub phase_number: i32,
     pub next_run: Option<i64>,
+    pub card: i32,
+}
+
+/// Schedule restored from export with the whole state of its algorithm.
+#[derive(Insertable)]
+#[table_name = "schedules"]
+pub struct RestoredSchedule<'a> {
+    pub memory_id: i32,
+    pub card: i32,
+    pub phase_number: i32,
+    pub next_run: Option<i64>,
+    pub ease_factor: f64,
+    pub interval_secs: i64,
+    pub repetitions: i32,
+    pub stability: Option<f64>,
+    pub difficulty: Option<f64>,
+    pub status: &'a str,
+    pub attempts: i32,
+    pub last_error: Option<&'a str>,
 }
 
 #[derive(Queryable, Serialize, Deserialize, Debug)]
@@ -51,12 +193,32 @@ pub struct Schedule {
     pub memory_id: i32,
     pub phase_number: i32,
     pub next_run: Option<i64>,
+    pub ease_factor: f64,
+    pub interval_secs: i64,
+    pub repetitions: i32,
+    pub stability: Option<f64>,
+    pub difficulty: Option<f64>,
+    pub locked_by: Option<String>,
+    pub locked_until: Option<i64>,
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    /// Cloze index the schedule is for, 0 for the whole memory.
+    pub card: i32,
 }
 
 #[derive(Queryable, Serialize, Deserialize, Debug)]
 pub struct MemoryWithUser {
     pub memory: Memory,
     pub user: User,
+    pub deck: Option<Deck>,
+}
+
+/// Memory with schedules of its cards that are not retired.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MemoryWithSchedules {
+    pub memory: Memory,
+    pub schedules: Vec<Schedule>,
 }
 
 #[derive(Queryable, Serialize, Deserialize, Debug)]
@@ -64,3 +226,80 @@ pub struct ScheduleWithMemoryAndUser {
     pub schedule: Schedule,
     pub memory_with_user: MemoryWithUser,
 }
+
+#[derive(Queryable, Serialize, Deserialize, Debug)]
+pub struct Review {
+    pub id: i32,
+    pub schedule_id: i32,
+    pub phase_number: i32,
+    pub sent_at: i64,
+    pub channel: String,
+    pub delivery_status: String,
+    pub grade: Option<String>,
+    pub response_latency: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[table_name = "reviews"]
+pub struct NewReview<'a> {
+    pub schedule_id: i32,
+    pub phase_number: i32,
+    pub sent_at: i64,
+    pub channel: &'a str,
+    pub delivery_status: &'a str,
+    pub grade: Option<&'a str>,
+    pub response_latency: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeliveryStatus {
+    Sent,
+    Failed,
+    /// Grade was given without a reminder to answer.
+    NotSent,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::NotSent => "not_sent",
+        }
+    }
+}
+
+/// Channels reviews are recorded with: names of notifiers and `api` for grades given without a reminder.
+pub const REVIEW_CHANNELS: [&str; 4] = ["smtp", "file", "stub", "api"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScheduleStatus {
+    Active,
+    /// Paused by user, waits to be resumed.
+    Paused,
+    /// Delivery failed too many times, waits for admin to requeue it.
+    Failed,
+    /// Cloze of the card was removed from memory text, the schedule keeps its history.
+    Retired,
+}
+
+impl ScheduleStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ScheduleStatus::Active => "active",
+            ScheduleStatus::Paused => "paused",
+            ScheduleStatus::Failed => "failed",
+            ScheduleStatus::Retired => "retired",
+        }
+    }
+}
+
+/// Change of memory schedule requested by user.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScheduleAction {
+    Pause,
+    /// Continues paused schedule from the current phase.
+    Resume,
+    /// Starts schedule over from the first phase.
+    Reset,
+}
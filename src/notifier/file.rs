@@ -0,0 +1,104 @@
+use super::{Notification, Notifier};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+/// Drops every notification as a separate file into a directory, HTML part goes into a file next to it.
+pub struct FileNotifier {
+    dir: PathBuf,
+    counter: AtomicUsize,
+}
+
+impl FileNotifier {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> FileNotifier {
+        FileNotifier {
+            dir: dir.into(),
+            counter: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Notifier for FileNotifier {
+    fn notify(&self, notification: &Notification) -> Result<(), failure::Error> {
+        fs::create_dir_all(&self.dir)?;
+
+        let secs = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        let n = self.counter.fetch_add(1, Ordering::SeqCst);
+        let name = format!("{}-{}-{}", secs, n, file_name(notification.address));
+        let path = self.dir.join(format!("{}.eml", name));
+
+        let unsubscribe = notification
+            .unsubscribe
+            .map(|link| format!("List-Unsubscribe: <{}>\n", link))
+            .unwrap_or_default();
+        let content = format!(
+            "To: {}\nSubject: {}\n{}\n{}\n",
+            notification.address,
+            notification.topic.unwrap_or_default(),
+            unsubscribe,
+            notification.text
+        );
+        fs::write(path, content)?;
+        if let Some(html) = notification.html {
+            fs::write(self.dir.join(format!("{}.html", name)), html)?;
+        }
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "file"
+    }
+}
+
+/// Keeps only characters that can't leave the directory or break the name, e.g. `/`.
+fn file_name(address: &str) -> String {
+    address
+        .chars()
+        .map(|c| match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '@' | '.' | '_' | '-' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_one_file_per_notification() {
+        let dir = std::env::temp_dir().join(format!("file_notifier_{}", std::process::id()));
+        let notifier = FileNotifier::new(&dir);
+        let notification = Notification {
+            address: "vasia@ya.ru",
+            topic: None,
+            text: "ownership",
+            html: None,
+            unsubscribe: Some("http://localhost:8080/unsubscribe/users/1/abc"),
+        };
+        notifier.notify(&notification).unwrap();
+        notifier.notify(&notification).unwrap();
+
+        let files: Vec<_> = fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(files.len(), 2);
+        let content = fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+        assert!(content.contains("To: vasia@ya.ru"));
+        assert!(
+            content.contains("List-Unsubscribe: <http://localhost:8080/unsubscribe/users/1/abc>")
+        );
+        assert!(content.ends_with("ownership\n"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn address_does_not_escape_directory() {
+        assert_eq!(file_name("vasia@ya.ru"), "vasia@ya.ru");
+        assert_eq!(file_name("../../etc/passwd"), ".._.._etc_passwd");
+        assert_eq!(file_name("a b/\\c@x"), "a_b__c@x");
+    }
+}
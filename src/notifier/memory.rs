@@ -0,0 +1,79 @@
+use super::{Notification, Notifier};
+use log::info;
+#[cfg(test)]
+use std::sync::Mutex;
+
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentNotification {
+    pub address: String,
+    pub topic: Option<String>,
+    pub text: String,
+}
+
+/// Logs notifications instead of sending them, so schedule loop can run without a mail server.
+/// Tests also find sent notifications in memory, the service doesn't keep them.
+#[derive(Default)]
+pub struct MemoryNotifier {
+    #[cfg(test)]
+    sent: Mutex<Vec<SentNotification>>,
+}
+
+impl MemoryNotifier {
+    pub fn new() -> MemoryNotifier {
+        MemoryNotifier::default()
+    }
+
+    #[cfg(test)]
+    pub fn sent(&self) -> Vec<SentNotification> {
+        self.sent.lock().expect("notifier lock poisoned").clone()
+    }
+}
+
+impl Notifier for MemoryNotifier {
+    fn notify(&self, notification: &Notification) -> Result<(), failure::Error> {
+        info!("stub notification to '{}'", notification.address);
+        #[cfg(test)]
+        self.sent
+            .lock()
+            .expect("notifier lock poisoned")
+            .push(SentNotification {
+                address: notification.address.to_string(),
+                topic: notification.topic.map(|t| t.to_string()),
+                text: notification.text.to_string(),
+            });
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "stub"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_sent_notifications() {
+        let notifier = MemoryNotifier::new();
+        notifier
+            .notify(&Notification {
+                address: "vasia@ya.ru",
+                topic: Some("rust"),
+                text: "borrow checker",
+                html: None,
+                unsubscribe: None,
+            })
+            .unwrap();
+
+        assert_eq!(
+            notifier.sent(),
+            vec![SentNotification {
+                address: "vasia@ya.ru".to_string(),
+                topic: Some("rust".to_string()),
+                text: "borrow checker".to_string(),
+            }]
+        );
+    }
+}
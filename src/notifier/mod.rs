@@ -0,0 +1,63 @@
+mod file;
+mod memory;
+mod smtp;
+
+pub use file::FileNotifier;
+pub use memory::MemoryNotifier;
+pub use smtp::{MailConfig, SmtpNotifier};
+
+use std::env;
+use std::sync::Arc;
+
+#[derive(Debug, Fail)]
+pub enum NotifierError {
+    #[fail(
+        display = "unknown notifier '{}', expected one of: smtp, stub, file",
+        name
+    )]
+    UnknownNotifier { name: String },
+    #[fail(display = "NOTIFIER_DIR must be set for 'file' notifier")]
+    NoNotifierDir,
+    #[fail(display = "{} must be set", name)]
+    MissingVar { name: &'static str },
+    #[fail(display = "invalid value '{}' of {}", value, name)]
+    InvalidVar { name: &'static str, value: String },
+    #[fail(display = "fail to deliver notification to '{}': {}", address, reason)]
+    DeliveryFailed { address: String, reason: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct Notification<'a> {
+    pub address: &'a str,
+    pub topic: Option<&'a str>,
+    pub text: &'a str,
+    /// HTML version of `text`, sent as alternative part.
+    pub html: Option<&'a str>,
+    /// Link that stops reminders in one click, sent in `List-Unsubscribe` header (RFC 8058).
+    pub unsubscribe: Option<&'a str>,
+}
+
+/// Channel that delivers reminders to users.
+pub trait Notifier: Send + Sync {
+    fn notify(&self, notification: &Notification) -> Result<(), failure::Error>;
+
+    /// Releases resources kept between notifications, called after every scheduler tick.
+    fn close(&self) {}
+
+    /// Name of the channel, used in logs.
+    fn name(&self) -> &'static str;
+}
+
+/// Builds notifier selected by `NOTIFIER` env variable (`smtp` by default).
+pub fn from_env() -> Result<Arc<dyn Notifier>, NotifierError> {
+    let name = env::var("NOTIFIER").unwrap_or_else(|_| "smtp".to_string());
+    match name.as_str() {
+        "smtp" => Ok(Arc::new(SmtpNotifier::new(MailConfig::from_env()?))),
+        "stub" => Ok(Arc::new(MemoryNotifier::new())),
+        "file" => {
+            let dir = env::var("NOTIFIER_DIR").map_err(|_| NotifierError::NoNotifierDir)?;
+            Ok(Arc::new(FileNotifier::new(dir)))
+        }
+        _ => Err(NotifierError::UnknownNotifier { name }),
+    }
+}
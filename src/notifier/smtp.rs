@@ -0,0 +1,183 @@
+use super::{Notification, Notifier, NotifierError};
+use lettre::smtp::authentication::Credentials;
+use lettre::smtp::ConnectionReuseParameters;
+use lettre::{ClientSecurity, ClientTlsParameters, SendableEmail};
+use lettre::{SmtpClient, SmtpTransport, Transport};
+use lettre_email::EmailBuilder;
+use native_tls::TlsConnector;
+use std::env;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TlsMode {
+    None,
+    StartTls,
+    Implicit,
+}
+
+#[derive(Debug, Clone)]
+pub struct MailConfig {
+    pub host: String,
+    pub port: u16,
+    pub tls: TlsMode,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from_address: String,
+    pub from_name: Option<String>,
+    pub timeout: Duration,
+}
+
+impl MailConfig {
+    /// Reads `SMTP_*` and `MAIL_FROM_*` variables, only `SMTP_HOST` and `MAIL_FROM_ADDRESS` are required.
+    pub fn from_env() -> Result<MailConfig, NotifierError> {
+        let host = required_var("SMTP_HOST")?;
+        let tls = match optional_var("SMTP_TLS").as_deref() {
+            None | Some("starttls") => TlsMode::StartTls,
+            Some("implicit") => TlsMode::Implicit,
+            Some("none") => TlsMode::None,
+            Some(other) => return Err(invalid_var("SMTP_TLS", other)),
+        };
+        let port = match optional_var("SMTP_PORT") {
+            Some(port) => port.parse().map_err(|_| invalid_var("SMTP_PORT", &port))?,
+            None => match tls {
+                TlsMode::None => 25,
+                TlsMode::StartTls => 587,
+                TlsMode::Implicit => 465,
+            },
+        };
+        let timeout = match optional_var("SMTP_TIMEOUT_SECS") {
+            Some(secs) => Duration::from_secs(
+                secs.parse()
+                    .map_err(|_| invalid_var("SMTP_TIMEOUT_SECS", &secs))?,
+            ),
+            None => Duration::from_secs(30),
+        };
+
+        Ok(MailConfig {
+            host,
+            port,
+            tls,
+            username: optional_var("SMTP_USERNAME"),
+            password: optional_var("SMTP_PASSWORD"),
+            from_address: required_var("MAIL_FROM_ADDRESS")?,
+            from_name: optional_var("MAIL_FROM_NAME"),
+            timeout,
+        })
+    }
+}
+
+fn required_var(name: &'static str) -> Result<String, NotifierError> {
+    optional_var(name).ok_or(NotifierError::MissingVar { name })
+}
+
+fn optional_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+fn invalid_var(name: &'static str, value: &str) -> NotifierError {
+    NotifierError::InvalidVar {
+        name,
+        value: value.to_string(),
+    }
+}
+
+/// Sends emails reusing one SMTP connection until `close` is called.
+pub struct SmtpNotifier {
+    config: MailConfig,
+    transport: Mutex<Option<SmtpTransport>>,
+}
+
+impl SmtpNotifier {
+    pub fn new(config: MailConfig) -> SmtpNotifier {
+        SmtpNotifier {
+            config,
+            transport: Mutex::new(None),
+        }
+    }
+
+    fn build_transport(&self) -> Result<SmtpTransport, failure::Error> {
+        let config = &self.config;
+        let security = match config.tls {
+            TlsMode::None => ClientSecurity::None,
+            TlsMode::StartTls => ClientSecurity::Required(self.tls_parameters()?),
+            TlsMode::Implicit => ClientSecurity::Wrapper(self.tls_parameters()?),
+        };
+
+        let mut client = SmtpClient::new((config.host.as_str(), config.port), security)?
+            .timeout(Some(config.timeout))
+            .connection_reuse(ConnectionReuseParameters::ReuseUnlimited);
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            client = client.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(client.transport())
+    }
+
+    fn tls_parameters(&self) -> Result<ClientTlsParameters, failure::Error> {
+        Ok(ClientTlsParameters::new(
+            self.config.host.clone(),
+            TlsConnector::new()?,
+        ))
+    }
+
+    fn build_email(&self, notification: &Notification) -> Result<SendableEmail, failure::Error> {
+        let from = match &self.config.from_name {
+            Some(name) => {
+                EmailBuilder::new().from((self.config.from_address.as_str(), name.as_str()))
+            }
+            None => EmailBuilder::new().from(self.config.from_address.as_str()),
+        };
+
+        let email = from
+            .to(notification.address)
+            .subject(notification.topic.unwrap_or_default());
+        let mut email = match notification.html {
+            Some(html) => email.alternative(html, notification.text),
+            None => email.text(notification.text),
+        };
+        if let Some(link) = notification.unsubscribe {
+            email = email
+                .header(("List-Unsubscribe", format!("<{}>", link)))
+                .header(("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"));
+        }
+
+        Ok(email.build()?.into())
+    }
+}
+
+impl Notifier for SmtpNotifier {
+    fn notify(&self, notification: &Notification) -> Result<(), failure::Error> {
+        let email = self.build_email(notification)?;
+
+        let mut transport = self.transport.lock().expect("smtp transport lock poisoned");
+        if transport.is_none() {
+            *transport = Some(self.build_transport()?);
+        }
+        let mailer = transport.as_mut().expect("smtp transport was just created");
+
+        if let Err(err) = mailer.send(email) {
+            // connection may be broken, next notification will reconnect
+            mailer.close();
+            *transport = None;
+            return Err(NotifierError::DeliveryFailed {
+                address: notification.address.to_string(),
+                reason: err.to_string(),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    fn close(&self) {
+        let mut transport = self.transport.lock().expect("smtp transport lock poisoned");
+        if let Some(mut mailer) = transport.take() {
+            mailer.close();
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "smtp"
+    }
+}
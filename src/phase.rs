@@ -1,4 +1,10 @@
-use crate::models::Phase;
+use crate::db_actions;
+use crate::models::{MemoryWithUser, Phase};
+use crate::scheduling::Grade;
+use diesel::pg::PgConnection;
+use log::error;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
 
 #[derive(Debug, Fail)]
 pub enum PhaseError {
@@ -8,6 +14,130 @@ pub enum PhaseError {
     SequenceError,
     #[fail(display = "empty sequence")]
     Empty,
+    #[fail(display = "unknown phase set '{}'", name)]
+    UnknownSet { name: String },
+}
+
+/// Phase set used when neither deck nor user chose one.
+pub const DEFAULT_PHASE_SET: &str = "default";
+
+/// Named phase sets, every one of them is a valid `Phases` sequence.
+pub struct PhaseSets {
+    sets: BTreeMap<String, Phases>,
+}
+
+impl PhaseSets {
+    /// Groups phases by their set and validates every set, the default one must be among them.
+    pub fn new(phases: Vec<Phase>) -> Result<PhaseSets, PhaseError> {
+        let mut grouped: BTreeMap<String, Vec<Phase>> = BTreeMap::new();
+        for phase in phases {
+            grouped
+                .entry(phase.phase_set.clone())
+                .or_default()
+                .push(phase);
+        }
+        if !grouped.contains_key(DEFAULT_PHASE_SET) {
+            return Err(PhaseError::UnknownSet {
+                name: DEFAULT_PHASE_SET.to_string(),
+            });
+        }
+
+        let mut sets = BTreeMap::new();
+        for (name, set_phases) in grouped {
+            let phases = Phases::new(set_phases).map_err(|err| {
+                error!("phase set '{}' is invalid: {}", name, err);
+                err
+            })?;
+            sets.insert(name, phases);
+        }
+
+        Ok(PhaseSets { sets })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Phases> {
+        self.sets.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Phases)> {
+        self.sets
+            .iter()
+            .map(|(name, phases)| (name.as_str(), phases))
+    }
+
+    /// Checks that phase set with `name` exists.
+    pub fn check(&self, name: &str) -> Result<(), PhaseError> {
+        match self.get(name) {
+            Some(_) => Ok(()),
+            None => Err(PhaseError::UnknownSet {
+                name: name.to_string(),
+            }),
+        }
+    }
+
+    /// Set with `name`, or the default one when there is no such set.
+    pub fn get_or_default(&self, name: &str) -> &Phases {
+        self.sets.get(name).unwrap_or(&self.sets[DEFAULT_PHASE_SET])
+    }
+
+    /// Set chosen for memory's deck, or for its owner when deck doesn't choose one.
+    /// Unknown set, e.g. removed one, falls back to the default set.
+    pub fn for_memory(&self, memory_with_user: &MemoryWithUser) -> &Phases {
+        &self.sets[self.name_for_memory(memory_with_user)]
+    }
+
+    /// Name of the set `for_memory` picks.
+    pub fn name_for_memory(&self, memory_with_user: &MemoryWithUser) -> &str {
+        let deck_set = memory_with_user
+            .deck
+            .as_ref()
+            .and_then(|d| d.phase_set.as_deref());
+        let name = deck_set
+            .or(memory_with_user.user.phase_set.as_deref())
+            .unwrap_or(DEFAULT_PHASE_SET);
+
+        match self.sets.get_key_value(name) {
+            Some((known, _)) => known,
+            None => {
+                error!(
+                    "unknown phase set '{}' of memory with id '{}', use the default one",
+                    name, memory_with_user.memory.id
+                );
+                DEFAULT_PHASE_SET
+            }
+        }
+    }
+}
+
+/// Phase sets that can be replaced while service is running.
+pub struct ReloadablePhaseSets {
+    current: RwLock<Arc<PhaseSets>>,
+}
+
+impl ReloadablePhaseSets {
+    pub fn new(sets: PhaseSets) -> ReloadablePhaseSets {
+        ReloadablePhaseSets {
+            current: RwLock::new(Arc::new(sets)),
+        }
+    }
+
+    /// Sets as they are now, a caller keeps working with them even if they are replaced meanwhile.
+    pub fn current(&self) -> Arc<PhaseSets> {
+        self.current
+            .read()
+            .expect("phase sets lock poisoned")
+            .clone()
+    }
+
+    pub fn replace(&self, sets: Arc<PhaseSets>) {
+        *self.current.write().expect("phase sets lock poisoned") = sets;
+    }
+
+    /// Reads and validates sets from DB and swaps them in, current sets are kept when new ones are invalid.
+    pub fn reload(&self, conn: &PgConnection) -> Result<Arc<PhaseSets>, failure::Error> {
+        let sets = Arc::new(db_actions::get_phase_sets(conn)?);
+        self.replace(sets.clone());
+        Ok(sets)
+    }
 }
 
 pub struct Phases {
@@ -33,4 +163,104 @@ impl Phases {
     pub fn get(&self, phase_num: i32) -> Option<&Phase> {
         self.phases.iter().find(|&ph| ph.number == phase_num)
     }
+
+    /// Phase to deliver next, when user graded a memory which next delivery is `next_phase`.
+    /// Failed recall restarts the curve, hard one repeats last interval and easy one skips a phase.
+    pub fn phase_after_review(&self, next_phase: i32, grade: Grade) -> i32 {
+        let first = self.first_number();
+        let restart = (first + 1).min(self.last_number());
+        match grade {
+            Grade::Again => restart,
+            Grade::Hard => (next_phase - 1).max(restart),
+            Grade::Good => next_phase,
+            Grade::Easy => next_phase + 1,
+        }
+    }
+
+    /// Time of the next run for a phase counted `from_secs`, `None` when phases are over.
+    pub fn next_run(&self, phase_num: i32, from_secs: i64) -> Option<i64> {
+        self.get(phase_num).map(|ph| from_secs + ph.seconds_to_wait)
+    }
+
+    pub fn first_number(&self) -> i32 {
+        self.phases[0].number
+    }
+
+    pub fn last_number(&self) -> i32 {
+        self.phases[self.count - 1].number
+    }
+
+    /// Nearest phase of this set, e.g. for a schedule which came from another set.
+    pub fn clamp(&self, phase_num: i32) -> i32 {
+        phase_num.clamp(self.first_number(), self.last_number())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(name: &str, numbers: std::ops::RangeInclusive<i32>) -> Vec<Phase> {
+        numbers
+            .map(|number| Phase {
+                id: number,
+                number,
+                seconds_to_wait: number as i64 * 60,
+                phase_set: name.to_string(),
+            })
+            .collect()
+    }
+
+    fn phases(count: i32) -> Phases {
+        Phases::new(set(DEFAULT_PHASE_SET, 1..=count)).unwrap()
+    }
+
+    #[test]
+    fn review_moves_next_phase() {
+        let phases = phases(7);
+        assert_eq!(phases.phase_after_review(5, Grade::Again), 2);
+        assert_eq!(phases.phase_after_review(5, Grade::Hard), 4);
+        assert_eq!(phases.phase_after_review(2, Grade::Hard), 2);
+        assert_eq!(phases.phase_after_review(5, Grade::Good), 5);
+        assert_eq!(phases.phase_after_review(5, Grade::Easy), 6);
+        assert_eq!(phases.phase_after_review(8, Grade::Hard), 7);
+    }
+
+    #[test]
+    fn review_with_single_phase() {
+        let phases = phases(1);
+        assert_eq!(phases.phase_after_review(2, Grade::Again), 1);
+        assert_eq!(phases.phase_after_review(2, Grade::Hard), 1);
+    }
+
+    #[test]
+    fn next_run_after_last_phase_is_none() {
+        let phases = phases(3);
+        assert_eq!(phases.next_run(3, 100), Some(280));
+        assert_eq!(phases.next_run(4, 100), None);
+    }
+
+    #[test]
+    fn sets_are_validated_separately() {
+        let mut phases = set(DEFAULT_PHASE_SET, 1..=7);
+        phases.extend(set("cram", 1..=3));
+        let sets = PhaseSets::new(phases).unwrap();
+        assert_eq!(sets.get(DEFAULT_PHASE_SET).map(|p| p.count), Some(7));
+        assert_eq!(sets.get("cram").map(|p| p.count), Some(3));
+        assert!(sets.check("vocab").is_err());
+
+        let mut broken = set(DEFAULT_PHASE_SET, 1..=7);
+        broken.extend(set("cram", 1..=1));
+        broken.extend(set("cram", 3..=4));
+        assert!(PhaseSets::new(broken).is_err());
+        assert!(PhaseSets::new(set("cram", 1..=3)).is_err());
+    }
+
+    #[test]
+    fn clamp_to_set() {
+        let phases = phases(3);
+        assert_eq!(phases.clamp(0), 1);
+        assert_eq!(phases.clamp(2), 2);
+        assert_eq!(phases.clamp(7), 3);
+    }
 }
@@ -0,0 +1,144 @@
+use crate::models::User;
+use crate::scheduling::ScheduleState;
+use chrono::{Duration, NaiveTime, TimeZone};
+use chrono_tz::Tz;
+use log::error;
+
+#[derive(Debug, Fail)]
+pub enum QuietHoursError {
+    #[fail(display = "unknown timezone '{}'", name)]
+    UnknownTimezone { name: String },
+}
+
+pub fn parse_timezone(name: &str) -> Result<Tz, QuietHoursError> {
+    name.parse::<Tz>()
+        .map_err(|_| QuietHoursError::UnknownTimezone {
+            name: name.to_string(),
+        })
+}
+
+/// Part of a day in user's timezone when reminders are not delivered, may go over midnight.
+pub struct QuietHours {
+    timezone: Tz,
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl QuietHours {
+    pub fn new(timezone: Tz, start: NaiveTime, end: NaiveTime) -> QuietHours {
+        QuietHours {
+            timezone,
+            start,
+            end,
+        }
+    }
+
+    pub fn for_user(user: &User) -> Result<Option<QuietHours>, QuietHoursError> {
+        match (user.quiet_start, user.quiet_end) {
+            (Some(start), Some(end)) if start != end => Ok(Some(QuietHours::new(
+                parse_timezone(&user.timezone)?,
+                start,
+                end,
+            ))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Moves `at_secs` to the end of quiet hours when it falls inside them.
+    pub fn defer(&self, at_secs: i64) -> i64 {
+        let local = self.timezone.timestamp(at_secs, 0);
+        let time = local.time();
+        let date = local.date().naive_local();
+
+        let end_date = if self.start < self.end {
+            if time < self.start || time >= self.end {
+                return at_secs;
+            }
+            date
+        } else if time >= self.start {
+            date.succ()
+        } else if time < self.end {
+            date
+        } else {
+            return at_secs;
+        };
+
+        let end = end_date.and_time(self.end);
+        // end of quiet hours may fall into a gap of daylight saving switch
+        let end_local = self
+            .timezone
+            .from_local_datetime(&end)
+            .earliest()
+            .or_else(|| {
+                self.timezone
+                    .from_local_datetime(&(end + Duration::hours(1)))
+                    .earliest()
+            });
+
+        end_local
+            .map(|end| end.timestamp().max(at_secs))
+            .unwrap_or(at_secs)
+    }
+}
+
+/// Moves next run of user's schedule out of user's quiet hours.
+pub fn defer_next_run(state: ScheduleState, user: &User) -> ScheduleState {
+    match QuietHours::for_user(user) {
+        Ok(Some(quiet)) => ScheduleState {
+            next_run: state.next_run.map(|at| quiet.defer(at)),
+            ..state
+        },
+        Ok(None) => state,
+        Err(err) => {
+            error!(
+                "ignore quiet hours of user with id '{}', reason: '{}'",
+                user.id, err
+            );
+            state
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, Utc};
+
+    fn ts(tz: Tz, y: i32, m: u32, d: u32, h: u32, min: u32) -> i64 {
+        tz.ymd(y, m, d).and_hms(h, min, 0).timestamp()
+    }
+
+    fn hours(start: u32, end: u32, tz: Tz) -> QuietHours {
+        QuietHours::new(
+            tz,
+            NaiveTime::from_hms(start, 0, 0),
+            NaiveTime::from_hms(end, 0, 0),
+        )
+    }
+
+    #[test]
+    fn outside_quiet_hours_is_kept() {
+        let quiet = hours(22, 7, chrono_tz::Europe::Moscow);
+        let at = ts(chrono_tz::Europe::Moscow, 2020, 4, 20, 12, 30);
+        assert_eq!(quiet.defer(at), at);
+    }
+
+    #[test]
+    fn night_is_deferred_to_morning() {
+        let tz = chrono_tz::Europe::Moscow;
+        let quiet = hours(22, 7, tz);
+        let morning = ts(tz, 2020, 4, 21, 7, 0);
+        assert_eq!(quiet.defer(ts(tz, 2020, 4, 20, 23, 15)), morning);
+        assert_eq!(quiet.defer(ts(tz, 2020, 4, 21, 3, 0)), morning);
+    }
+
+    #[test]
+    fn window_within_one_day() {
+        let quiet = hours(13, 15, Tz::UTC);
+        let at = Utc.ymd(2020, 4, 20).and_hms(14, 0, 0).timestamp();
+        let end = NaiveDate::from_ymd(2020, 4, 20)
+            .and_hms(15, 0, 0)
+            .timestamp();
+        assert_eq!(quiet.defer(at), end);
+    }
+}
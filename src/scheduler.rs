@@ -1,8 +1,20 @@
+use crate::cards;
 use crate::db_actions;
-use crate::phase::Phases;
+use crate::delivery::{self, DeliveryMode};
+use crate::links::Links;
+use crate::models::{DeliveryStatus, NewReview, Schedule, ScheduleWithMemoryAndUser};
+use crate::notifier::{Notification, Notifier};
+use crate::phase::{PhaseSets, ReloadablePhaseSets};
+use crate::scheduling::{self, AlgorithmKind, ScheduleState};
+use crate::templates::{self, GradeLinks, MemoryContext, ReminderContext, Templates};
 use crate::DbPool;
 use diesel::pg::PgConnection;
 use log::{debug, error, info};
+use std::collections::BTreeMap;
+use std::env;
+use std::process;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 use std::time::SystemTime;
 
@@ -10,98 +22,446 @@ use std::time::SystemTime;
 #[fail(display = "fail to check schedulers. todo: fixme")]
 struct RunError;
 
-pub fn start_checking_thread(phases: Phases, sleep_duration: Duration, pool: DbPool) {
+#[derive(Debug, Fail)]
+#[fail(display = "invalid {} '{}'", name, value)]
+pub struct ConfigError {
+    name: &'static str,
+    value: String,
+}
+
+pub fn var_or<T: FromStr>(name: &'static str, default: T) -> Result<T, ConfigError> {
+    match env::var(name) {
+        Ok(value) => value.parse().map_err(|_| ConfigError { name, value }),
+        Err(_) => Ok(default),
+    }
+}
+
+/// How many due schedules one instance takes per tick.
+const CLAIM_LIMIT: i64 = 100;
+
+/// Identity under which this service instance holds due schedules, so several instances can run together.
+pub struct Lease {
+    pub owner: String,
+    pub duration: Duration,
+}
+
+impl Lease {
+    /// Owner is `WORKER_ID` or host name with process id, duration is `SCHEDULER_LEASE_SECS` (5 minutes by default).
+    pub fn from_env() -> Result<Lease, ConfigError> {
+        let owner = env::var("WORKER_ID").unwrap_or_else(|_| {
+            let host = env::var("HOSTNAME").unwrap_or_else(|_| "localhost".to_string());
+            format!("{}-{}", host, process::id())
+        });
+        let duration = Duration::from_secs(var_or("SCHEDULER_LEASE_SECS", 5 * 60)?);
+
+        Ok(Lease { owner, duration })
+    }
+}
+
+/// Exponential backoff for failed deliveries.
+pub struct RetryPolicy {
+    pub max_attempts: i32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Reads `DELIVERY_MAX_ATTEMPTS` (5 by default), `DELIVERY_RETRY_BASE_SECS` (1 minute by default)
+    /// and `DELIVERY_RETRY_MAX_SECS` (6 hours by default).
+    pub fn from_env() -> Result<RetryPolicy, ConfigError> {
+        Ok(RetryPolicy {
+            max_attempts: var_or("DELIVERY_MAX_ATTEMPTS", 5)?,
+            base_delay: Duration::from_secs(var_or("DELIVERY_RETRY_BASE_SECS", 60)?),
+            max_delay: Duration::from_secs(var_or("DELIVERY_RETRY_MAX_SECS", 6 * 60 * 60)?),
+        })
+    }
+
+    /// Time of the next attempt after `failed_attempts` in a row, `None` when it is time to give up.
+    pub fn retry_at(&self, failed_attempts: i32, now: i64) -> Option<i64> {
+        if failed_attempts >= self.max_attempts {
+            return None;
+        }
+        let factor = 1u32
+            .checked_shl(failed_attempts.max(1) as u32 - 1)
+            .unwrap_or(u32::MAX);
+        let delay = self
+            .base_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+
+        Some(now + delay.as_secs() as i64)
+    }
+}
+
+/// Re-reads phase sets every `period`, so changed intervals apply without restart.
+/// Zero period turns reloading off.
+pub fn start_reloading_thread(
+    phase_sets: Arc<ReloadablePhaseSets>,
+    period: Duration,
+    pool: DbPool,
+) {
+    if period.as_secs() == 0 {
+        return;
+    }
+    let mut sleep_interval = tokio::time::interval(period);
+    tokio::spawn(async move {
+        // the first tick completes immediately, sets were just loaded
+        sleep_interval.tick().await;
+        loop {
+            sleep_interval.tick().await;
+            let conn = pool.get().expect("couldn't get db connection from pool");
+            match phase_sets.reload(&conn) {
+                Ok(_) => debug!("successfully reload phase sets"),
+                Err(err) => error!("keep previous phase sets, reason: '{}'", err),
+            }
+        }
+    });
+}
+
+/// Everything the checking thread needs to deliver reminders, built once at start.
+pub struct Dispatcher {
+    pub notifier: Arc<dyn Notifier>,
+    pub links: Arc<Links>,
+    pub templates: Arc<Templates>,
+    pub lease: Lease,
+    pub retry: RetryPolicy,
+}
+
+pub fn start_checking_thread(
+    phase_sets: Arc<ReloadablePhaseSets>,
+    dispatcher: Dispatcher,
+    sleep_duration: Duration,
+    pool: DbPool,
+) {
     let mut sleep_interval = tokio::time::interval(sleep_duration);
     tokio::spawn(async move {
         loop {
             sleep_interval.tick().await;
             let conn = pool.get().expect("couldn't get db connection from pool");
-            match one_run(&phases, &conn) {
+            match one_run(&phase_sets.current(), &dispatcher, &conn) {
                 Ok(_) => debug!("successfully check all schedulers"),
                 Err(err) => error!("{}", err),
             }
+            dispatcher.notifier.close();
         }
     });
 }
 
-fn one_run(phases: &Phases, conn: &PgConnection) -> Result<(), failure::Error> {
+fn one_run(
+    phase_sets: &PhaseSets,
+    dispatcher: &Dispatcher,
+    conn: &PgConnection,
+) -> Result<(), failure::Error> {
     let curr_seconds = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .map_err(|_| RunError)?;
-    let schedulers =
-        db_actions::get_schedulers(curr_seconds.as_secs() as i64, conn).map_err(|_| RunError)?;
-
-    let schedulers_to_update = schedulers.iter().filter(|sch_with_memory| {
-        let email = &sch_with_memory.memory_with_user.user.email;
-        let topic = sch_with_memory.memory_with_user.memory.topic.as_deref();
-        let text = &sch_with_memory.memory_with_user.memory.text;
-        try_to_send_email(email, topic, text).is_ok()
-    });
+    let now = curr_seconds.as_secs() as i64;
+    let schedulers = db_actions::claim_schedulers(
+        now,
+        &dispatcher.lease.owner,
+        now + dispatcher.lease.duration.as_secs() as i64,
+        CLAIM_LIMIT,
+        conn,
+    )
+    .map_err(|_| RunError)?;
 
-    schedulers_to_update.for_each(|sch_with_memory| {
-        info!("scheduler to check: {:?}", sch_with_memory);
-        let next_phase_num = sch_with_memory.schedule.phase_number + 1;
-        let secs_to_add = phases.get(next_phase_num).map(|ph| ph.seconds_to_wait);
+    let tick = Tick {
+        phase_sets,
+        dispatcher,
+        now,
+        conn,
+    };
+
+    let mut by_user: BTreeMap<i32, Vec<Due>> = BTreeMap::new();
+    for sch_with_memory in schedulers.iter() {
+        match AlgorithmKind::resolve(
+            &sch_with_memory.memory_with_user.memory,
+            &sch_with_memory.memory_with_user.user,
+        ) {
+            Ok(kind) => by_user
+                .entry(sch_with_memory.memory_with_user.user.id)
+                .or_default()
+                .push((sch_with_memory, kind)),
+            Err(err) => {
+                error!(
+                    "skip schedule with id '{}', reason: '{}'",
+                    sch_with_memory.schedule.id, err
+                );
+                tick.fail(&sch_with_memory.schedule, &err.to_string());
+            }
+        }
+    }
 
-        let update_res = db_actions::update_schedule_time(
+    for due in by_user.values() {
+        let user = &due[0].0.memory_with_user.user;
+        let mode = user
+            .delivery_mode
+            .parse()
+            .unwrap_or(DeliveryMode::Immediate);
+        if mode == DeliveryMode::Immediate {
+            due.iter()
+                .for_each(|d| tick.deliver(std::slice::from_ref(d)));
+        } else {
+            tick.deliver(due);
+        }
+    }
+
+    Ok(())
+}
+
+/// Due schedule with algorithm that plans it.
+type Due<'a> = (&'a ScheduleWithMemoryAndUser, AlgorithmKind);
+
+/// Everything one scheduler run works with.
+struct Tick<'a> {
+    phase_sets: &'a PhaseSets,
+    dispatcher: &'a Dispatcher,
+    now: i64,
+    conn: &'a PgConnection,
+}
+
+impl<'a> Tick<'a> {
+    /// Sends one message about all `due` schedules of one user, several ones are sent as a digest.
+    fn deliver(&self, due: &[Due]) {
+        let user = &due[0].0.memory_with_user.user;
+        let planned: Vec<ScheduleState> = due
+            .iter()
+            .map(|(sch_with_memory, kind)| self.plan(sch_with_memory, *kind))
+            .collect();
+        let unsubscribe = self.dispatcher.links.unsubscribe_user(user.id);
+        let context = ReminderContext::new(
+            due.iter()
+                .zip(&planned)
+                .map(|((sch_with_memory, _), state)| self.memory_context(sch_with_memory, state))
+                .collect(),
+            unsubscribe.clone(),
+        );
+
+        let delivery = self
+            .dispatcher
+            .templates
+            .render(&context)
+            .and_then(|email| {
+                self.dispatcher.notifier.notify(&Notification {
+                    address: &user.email,
+                    topic: Some(&email.subject),
+                    text: &email.text,
+                    html: Some(&email.html),
+                    unsubscribe: Some(&unsubscribe),
+                })
+            });
+        for ((sch_with_memory, _), new_state) in due.iter().zip(planned) {
+            self.record(&sch_with_memory.schedule, delivery.is_ok());
+            match &delivery {
+                Ok(_) => self.advance(sch_with_memory, new_state),
+                Err(err) => {
+                    error!(
+                        "fail to notify about schedule with id '{}' via {}, reason: '{}'",
+                        sch_with_memory.schedule.id,
+                        self.dispatcher.notifier.name(),
+                        err
+                    );
+                    self.fail(&sch_with_memory.schedule, &err.to_string());
+                }
+            }
+        }
+    }
+
+    fn record(&self, schedule: &Schedule, delivered: bool) {
+        let status = if delivered {
+            DeliveryStatus::Sent
+        } else {
+            DeliveryStatus::Failed
+        };
+        let review = NewReview {
+            schedule_id: schedule.id,
+            phase_number: schedule.phase_number,
+            sent_at: self.now,
+            channel: self.dispatcher.notifier.name(),
+            delivery_status: status.as_str(),
+            grade: None,
+            response_latency: None,
+        };
+        if let Err(err) = db_actions::insert_review(&review, self.conn) {
+            error!(
+                "fail to record delivery of schedule with id '{}', reason: '{}'",
+                schedule.id, err
+            );
+        }
+    }
+
+    /// State of schedule after its reminder is delivered.
+    fn plan(
+        &self,
+        sch_with_memory: &ScheduleWithMemoryAndUser,
+        kind: AlgorithmKind,
+    ) -> ScheduleState {
+        let phases = self
+            .phase_sets
+            .for_memory(&sch_with_memory.memory_with_user);
+        delivery::plan_next_run(
+            scheduling::algorithm(kind, phases)
+                .after_delivery(&ScheduleState::from(&sch_with_memory.schedule), self.now),
+            &sch_with_memory.memory_with_user.user,
+        )
+    }
+
+    fn memory_context(
+        &self,
+        sch_with_memory: &ScheduleWithMemoryAndUser,
+        new_state: &ScheduleState,
+    ) -> MemoryContext {
+        let memory_with_user = &sch_with_memory.memory_with_user;
+        let memory = &memory_with_user.memory;
+        let schedule = &sch_with_memory.schedule;
+        let (text, reveal_link) = if cards::is_card(memory) {
+            (
+                cards::front(memory, schedule.card),
+                Some(self.dispatcher.links.reveal_card(schedule.id)),
+            )
+        } else {
+            (memory.text.clone(), None)
+        };
+        MemoryContext {
+            topic: memory.topic.clone(),
+            text_html: self.dispatcher.templates.text_html(&text, &memory.format),
+            text,
+            phase: schedule.phase_number,
+            total_phases: self.phase_sets.for_memory(memory_with_user).count,
+            deck: memory_with_user.deck.as_ref().map(|d| d.name.clone()),
+            next_review: new_state
+                .next_run
+                .map(|at| templates::local_time(at, &memory_with_user.user)),
+            grade_links: GradeLinks::new(&self.dispatcher.links, schedule.id),
+            reveal_link,
+            pause_link: self.dispatcher.links.pause_memory(memory.id),
+        }
+    }
+
+    fn advance(&self, sch_with_memory: &ScheduleWithMemoryAndUser, new_state: ScheduleState) {
+        info!("scheduler to check: {:?}", sch_with_memory);
+        let update_res = db_actions::complete_lease(
             sch_with_memory.schedule.id,
-            next_phase_num,
-            secs_to_add.map(|s| s + sch_with_memory.schedule.next_run.unwrap()),
-            conn,
+            &self.dispatcher.lease.owner,
+            &new_state,
+            self.conn,
         );
         match update_res {
-            Ok(_) => debug!(
-                "successfully update next run time for schedule with id '{}', add seconds '{}'",
-                sch_with_memory.schedule.id,
-                secs_to_add.unwrap_or(-1)
+            Ok(None) => error!(
+                "lease on schedule with id '{}' expired before it was updated",
+                sch_with_memory.schedule.id
+            ),
+            Ok(Some(_)) => debug!(
+                "successfully update next run time for schedule with id '{}', next run '{:?}'",
+                sch_with_memory.schedule.id, new_state.next_run
             ),
             Err(err) => error!(
                 "fail to updare next run time for schedule with id '{}', reason: '{}'",
                 sch_with_memory.schedule.id, err
             ),
         }
-    });
-
-    // todo: some logic with schedulers.
-    // 1. Send email
-    // 2. update phase and time.
+    }
 
-    Ok(())
+    fn fail(&self, schedule: &Schedule, reason: &str) {
+        let retry_at = self
+            .dispatcher
+            .retry
+            .retry_at(schedule.attempts + 1, self.now);
+        if retry_at.is_none() {
+            error!(
+                "give up on schedule with id '{}' after {} attempts",
+                schedule.id,
+                schedule.attempts + 1
+            );
+        }
+        let fail_res = db_actions::fail_lease(
+            schedule.id,
+            &self.dispatcher.lease.owner,
+            reason,
+            retry_at,
+            self.conn,
+        );
+        if let Err(err) = fail_res {
+            error!(
+                "fail to record failure of schedule with id '{}', reason: '{}'",
+                schedule.id, err
+            );
+        }
+    }
 }
 
-use lettre::smtp::authentication::Credentials;
-use lettre::SendableEmail;
-use lettre::SmtpTransport;
-use lettre::{SmtpClient, Transport};
-use lettre_email::EmailBuilder;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-#[derive(Debug, Fail)]
-#[fail(display = "fail to send email")]
-struct FailToSendEmail;
-
-fn try_to_send_email(
-    address: &str,
-    topic: Option<&str>,
-    text: &str,
-) -> Result<lettre::smtp::response::Response, failure::Error> {
-    let cred_email: String = "{your_email_here}".to_string();
-    let cred_password: String = "{password}".to_string();
-
-    let creds: Credentials = Credentials::new(cred_email.clone(), cred_password);
-
-    let mut mailer: SmtpTransport = SmtpClient::new_simple("smtp.gmail.com")
-        .unwrap()
-        .credentials(creds)
-        .transport();
-
-    let email: SendableEmail = EmailBuilder::new()
-        .to(address)
-        .from(cred_email)
-        .subject(topic.unwrap_or_default())
-        .text(text)
-        .build()
-        .unwrap()
-        .into();
-
-    Ok(mailer.send(email).map_err(|_| FailToSendEmail)?)
+    #[test]
+    fn retry_delay_doubles_up_to_limit() {
+        let retry = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_secs(60),
+            max_delay: Duration::from_secs(300),
+        };
+        assert_eq!(retry.retry_at(1, 0), Some(60));
+        assert_eq!(retry.retry_at(2, 0), Some(120));
+        assert_eq!(retry.retry_at(3, 0), Some(240));
+        assert_eq!(retry.retry_at(4, 0), Some(300));
+        assert_eq!(retry.retry_at(9, 0), Some(300));
+        assert_eq!(retry.retry_at(10, 0), None);
+    }
+
+    #[test]
+    #[ignore]
+    fn due_reminder_is_delivered_and_advanced() {
+        use crate::notifier::MemoryNotifier;
+        use crate::test_db;
+
+        let conn = test_db::connection();
+        let (user_id, _) = test_db::user("scheduled@example.com", &conn);
+        let memory_id = test_db::memory(user_id, "ownership", &conn);
+        let notifier = Arc::new(MemoryNotifier::new());
+        let dispatcher = Dispatcher {
+            notifier: notifier.clone(),
+            links: Arc::new(Links::new("http://localhost:8080", b"secret")),
+            templates: Arc::new(Templates::new(None).unwrap()),
+            lease: Lease {
+                owner: "test".to_string(),
+                duration: Duration::from_secs(60),
+            },
+            retry: RetryPolicy {
+                max_attempts: 1,
+                base_delay: Duration::from_secs(60),
+                max_delay: Duration::from_secs(60),
+            },
+        };
+        let phase_sets = db_actions::get_phase_sets(&conn).unwrap();
+        let started = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        one_run(&phase_sets, &dispatcher, &conn).unwrap();
+
+        let sent = notifier.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].address, "scheduled@example.com");
+        assert!(sent[0].text.contains("ownership"));
+
+        let history = db_actions::get_history(memory_id, &conn).unwrap().unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].phase_number, 1);
+        assert_eq!(history[0].channel, "stub");
+        assert_eq!(history[0].delivery_status, DeliveryStatus::Sent.as_str());
+
+        let memory = db_actions::get_memory(memory_id, &conn).unwrap().unwrap();
+        let schedule = &memory.schedules[0];
+        assert_eq!(schedule.phase_number, 2);
+        // the second phase of the default set waits 15 minutes
+        assert!(schedule.next_run.unwrap() >= started + 900);
+        assert_eq!(schedule.locked_by, None);
+
+        // nothing is due anymore
+        one_run(&phase_sets, &dispatcher, &conn).unwrap();
+        assert_eq!(notifier.sent().len(), 1);
+    }
 }
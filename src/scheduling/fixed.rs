@@ -0,0 +1,46 @@
+use super::{Grade, ScheduleState, SchedulingAlgorithm};
+use crate::phase::Phases;
+
+impl SchedulingAlgorithm for Phases {
+    /// Next phase is counted from the planned time of the delivered one, so phases keep the original rhythm.
+    /// When phases are over, schedule stays on the last one without next run.
+    fn after_delivery(&self, state: &ScheduleState, now: i64) -> ScheduleState {
+        let next_phase = self.clamp(state.phase_number) + 1;
+        ScheduleState {
+            phase_number: next_phase.min(self.last_number()),
+            next_run: self.next_run(next_phase, state.next_run.unwrap_or(now)),
+            ..state.clone()
+        }
+    }
+
+    fn after_review(&self, state: &ScheduleState, grade: Grade, now: i64) -> ScheduleState {
+        if grade == Grade::Good {
+            return state.clone();
+        }
+
+        // finished schedule stays on the last phase without next run
+        let phase_number = self.clamp(state.phase_number);
+        let next_phase = match state.next_run {
+            Some(_) => phase_number,
+            None => phase_number + 1,
+        };
+        let new_phase = self.phase_after_review(next_phase, grade);
+        ScheduleState {
+            phase_number: new_phase.min(self.last_number()),
+            next_run: self.next_run(new_phase, now),
+            ..state.clone()
+        }
+    }
+
+    /// Current phase is counted from `now`, finished schedule stays finished.
+    fn after_resume(&self, state: &ScheduleState, now: i64) -> ScheduleState {
+        let phase_number = self.clamp(state.phase_number);
+        ScheduleState {
+            phase_number,
+            next_run: state
+                .next_run
+                .and_then(|_| self.next_run(phase_number, now)),
+            ..state.clone()
+        }
+    }
+}
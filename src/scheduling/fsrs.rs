@@ -0,0 +1,172 @@
+use super::{Grade, ScheduleState, SchedulingAlgorithm, DAY_SECS};
+
+/// Free Spaced Repetition Scheduler (FSRS v4) with memory stability and difficulty per schedule.
+///
+/// Next repetition is planned when probability to recall the memory drops to `request_retention`.
+pub struct Fsrs {
+    pub w: [f64; 17],
+    pub request_retention: f64,
+}
+
+impl Fsrs {
+    pub const DEFAULT: Fsrs = Fsrs {
+        w: [
+            0.4, 0.6, 2.4, 5.8, 4.93, 0.94, 0.86, 0.01, 1.49, 0.14, 0.94, 2.18, 0.05, 0.34, 1.26,
+            0.29, 2.61,
+        ],
+        request_retention: 0.9,
+    };
+
+    fn rating(grade: Grade) -> f64 {
+        match grade {
+            Grade::Again => 1.0,
+            Grade::Hard => 2.0,
+            Grade::Good => 3.0,
+            Grade::Easy => 4.0,
+        }
+    }
+
+    fn init_difficulty(&self, grade: Grade) -> f64 {
+        clamp_difficulty(self.w[4] - (Fsrs::rating(grade) - 3.0) * self.w[5])
+    }
+
+    fn next_difficulty(&self, difficulty: f64, grade: Grade) -> f64 {
+        let d = difficulty - self.w[6] * (Fsrs::rating(grade) - 3.0);
+        // mean reversion to the difficulty of a new "good" memory
+        clamp_difficulty(self.w[7] * self.init_difficulty(Grade::Good) + (1.0 - self.w[7]) * d)
+    }
+
+    /// Probability to recall a memory with `stability` after `elapsed_days`.
+    fn retrievability(elapsed_days: f64, stability: f64) -> f64 {
+        1.0 / (1.0 + elapsed_days / (9.0 * stability))
+    }
+
+    fn recall_stability(&self, difficulty: f64, stability: f64, r: f64, grade: Grade) -> f64 {
+        let hard_penalty = if grade == Grade::Hard {
+            self.w[15]
+        } else {
+            1.0
+        };
+        let easy_bonus = if grade == Grade::Easy {
+            self.w[16]
+        } else {
+            1.0
+        };
+        stability
+            * (1.0
+                + self.w[8].exp()
+                    * (11.0 - difficulty)
+                    * stability.powf(-self.w[9])
+                    * ((self.w[10] * (1.0 - r)).exp() - 1.0)
+                    * hard_penalty
+                    * easy_bonus)
+    }
+
+    fn forget_stability(&self, difficulty: f64, stability: f64, r: f64) -> f64 {
+        self.w[11]
+            * difficulty.powf(-self.w[12])
+            * ((stability + 1.0).powf(self.w[13]) - 1.0)
+            * (self.w[14] * (1.0 - r)).exp()
+    }
+
+    fn interval_secs(&self, stability: f64) -> i64 {
+        let days = 9.0 * stability * (1.0 / self.request_retention - 1.0);
+        ((days * DAY_SECS as f64).round() as i64).max(DAY_SECS)
+    }
+
+    fn with_memory(
+        &self,
+        state: &ScheduleState,
+        stability: f64,
+        difficulty: f64,
+        now: i64,
+    ) -> ScheduleState {
+        let interval = self.interval_secs(stability);
+        ScheduleState {
+            next_run: Some(now + interval),
+            interval_secs: interval,
+            stability: Some(stability),
+            difficulty: Some(difficulty),
+            ..state.clone()
+        }
+    }
+}
+
+fn clamp_difficulty(difficulty: f64) -> f64 {
+    difficulty.clamp(1.0, 10.0)
+}
+
+impl SchedulingAlgorithm for Fsrs {
+    fn after_delivery(&self, state: &ScheduleState, now: i64) -> ScheduleState {
+        let (stability, difficulty) = match (state.stability, state.difficulty) {
+            (Some(s), Some(d)) => {
+                let elapsed_days = state.interval_secs as f64 / DAY_SECS as f64;
+                let r = Fsrs::retrievability(elapsed_days, s);
+                (
+                    self.recall_stability(d, s, r, Grade::Good),
+                    self.next_difficulty(d, Grade::Good),
+                )
+            }
+            _ => (self.w[2], self.init_difficulty(Grade::Good)),
+        };
+
+        ScheduleState {
+            repetitions: state.repetitions + 1,
+            ..self.with_memory(state, stability, difficulty, now)
+        }
+    }
+
+    /// Delivery already counted as "good" recall, so other grades shift memory from that point.
+    fn after_review(&self, state: &ScheduleState, grade: Grade, now: i64) -> ScheduleState {
+        let (s, d) = match (state.stability, state.difficulty) {
+            (Some(s), Some(d)) => (s, d),
+            // not delivered yet, grade is the first rating
+            _ => {
+                let s = self.w[Fsrs::rating(grade) as usize - 1];
+                return self.with_memory(state, s, self.init_difficulty(grade), now);
+            }
+        };
+
+        let difficulty = clamp_difficulty(d - self.w[6] * (Fsrs::rating(grade) - 3.0));
+        let stability = match grade {
+            Grade::Good => return state.clone(),
+            Grade::Again => self.forget_stability(difficulty, s, self.request_retention),
+            Grade::Hard => s * self.w[15],
+            Grade::Easy => s * self.w[16],
+        };
+
+        ScheduleState {
+            repetitions: if grade == Grade::Again {
+                0
+            } else {
+                state.repetitions
+            },
+            ..self.with_memory(state, stability, difficulty, now)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stability_grows_with_deliveries() {
+        let fsrs = Fsrs::DEFAULT;
+        let first = fsrs.after_delivery(&ScheduleState::new(1, 0), 0);
+        assert_eq!(first.stability, Some(2.4));
+        let second = fsrs.after_delivery(&first, first.next_run.unwrap());
+        assert!(second.stability.unwrap() > first.stability.unwrap());
+        assert!(second.interval_secs > first.interval_secs);
+    }
+
+    #[test]
+    fn again_shortens_interval() {
+        let fsrs = Fsrs::DEFAULT;
+        let state = fsrs.after_delivery(&fsrs.after_delivery(&ScheduleState::new(1, 0), 0), 0);
+        let reviewed = fsrs.after_review(&state, Grade::Again, 0);
+        assert!(reviewed.interval_secs < state.interval_secs);
+        assert!(reviewed.difficulty.unwrap() > state.difficulty.unwrap());
+        assert_eq!(fsrs.after_review(&state, Grade::Good, 0), state);
+    }
+}
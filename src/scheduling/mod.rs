@@ -0,0 +1,157 @@
+//! Algorithms deciding when memory should be repeated next.
+//!
+//! Delivery of a reminder counts as a successful ("good") recall, so schedule always moves forward
+//! even if user never grades it. When user grades a memory later, the grade corrects that assumption.
+
+mod fixed;
+mod fsrs;
+mod sm2;
+
+pub use fsrs::Fsrs;
+pub use sm2::Sm2;
+
+use crate::models::{Memory, Schedule, User};
+use crate::phase::Phases;
+use std::str::FromStr;
+
+pub const DAY_SECS: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Fail)]
+pub enum SchedulingError {
+    #[fail(display = "unknown scheduling algorithm '{}'", name)]
+    UnknownAlgorithm { name: String },
+}
+
+/// How well user recalled a memory.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Grade {
+    Again,
+    Hard,
+    Good,
+    Easy,
+}
+
+impl Grade {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Grade::Again => "again",
+            Grade::Hard => "hard",
+            Grade::Good => "good",
+            Grade::Easy => "easy",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlgorithmKind {
+    /// Fixed Ebbinghaus intervals from `phases` table.
+    Fixed,
+    /// SuperMemo SM-2.
+    Sm2,
+    /// Free Spaced Repetition Scheduler.
+    Fsrs,
+}
+
+impl AlgorithmKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AlgorithmKind::Fixed => "fixed",
+            AlgorithmKind::Sm2 => "sm2",
+            AlgorithmKind::Fsrs => "fsrs",
+        }
+    }
+
+    /// Algorithm set on memory, or user's one when memory doesn't override it.
+    pub fn resolve(memory: &Memory, user: &User) -> Result<AlgorithmKind, SchedulingError> {
+        memory
+            .algorithm
+            .as_deref()
+            .unwrap_or(&user.algorithm)
+            .parse()
+    }
+}
+
+impl FromStr for AlgorithmKind {
+    type Err = SchedulingError;
+
+    fn from_str(name: &str) -> Result<AlgorithmKind, SchedulingError> {
+        match name {
+            "fixed" => Ok(AlgorithmKind::Fixed),
+            "sm2" => Ok(AlgorithmKind::Sm2),
+            "fsrs" => Ok(AlgorithmKind::Fsrs),
+            _ => Err(SchedulingError::UnknownAlgorithm {
+                name: name.to_string(),
+            }),
+        }
+    }
+}
+
+/// Part of `schedules` row that algorithms work with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduleState {
+    pub phase_number: i32,
+    pub next_run: Option<i64>,
+    pub ease_factor: f64,
+    pub interval_secs: i64,
+    pub repetitions: i32,
+    pub stability: Option<f64>,
+    pub difficulty: Option<f64>,
+}
+
+impl ScheduleState {
+    /// State of a just added memory, which is delivered right away.
+    pub fn new(first_phase: i32, now: i64) -> ScheduleState {
+        ScheduleState {
+            phase_number: first_phase,
+            next_run: Some(now),
+            ease_factor: sm2::INITIAL_EASE_FACTOR,
+            interval_secs: 0,
+            repetitions: 0,
+            stability: None,
+            difficulty: None,
+        }
+    }
+}
+
+impl From<&Schedule> for ScheduleState {
+    fn from(schedule: &Schedule) -> ScheduleState {
+        ScheduleState {
+            phase_number: schedule.phase_number,
+            next_run: schedule.next_run,
+            ease_factor: schedule.ease_factor,
+            interval_secs: schedule.interval_secs,
+            repetitions: schedule.repetitions,
+            stability: schedule.stability,
+            difficulty: schedule.difficulty,
+        }
+    }
+}
+
+pub trait SchedulingAlgorithm {
+    /// State after reminder was delivered at `now`.
+    fn after_delivery(&self, state: &ScheduleState, now: i64) -> ScheduleState;
+
+    /// State after user graded the last delivered reminder at `now`.
+    fn after_review(&self, state: &ScheduleState, grade: Grade, now: i64) -> ScheduleState;
+
+    /// State after paused schedule is resumed at `now`, the current interval starts over.
+    fn after_resume(&self, state: &ScheduleState, now: i64) -> ScheduleState {
+        ScheduleState {
+            next_run: state.next_run.map(|_| now + state.interval_secs),
+            ..state.clone()
+        }
+    }
+}
+
+static SM2: Sm2 = Sm2;
+static FSRS: Fsrs = Fsrs::DEFAULT;
+
+pub fn algorithm(kind: AlgorithmKind, phases: &Phases) -> &dyn SchedulingAlgorithm {
+    match kind {
+        AlgorithmKind::Fixed => phases,
+        AlgorithmKind::Sm2 => &SM2,
+        AlgorithmKind::Fsrs => &FSRS,
+    }
+}
@@ -0,0 +1,97 @@
+use super::{Grade, ScheduleState, SchedulingAlgorithm, DAY_SECS};
+
+pub const INITIAL_EASE_FACTOR: f64 = 2.5;
+const MIN_EASE_FACTOR: f64 = 1.3;
+
+/// SuperMemo SM-2: interval grows by per-memory ease factor, which drops on hard recalls.
+pub struct Sm2;
+
+impl Sm2 {
+    fn quality(grade: Grade) -> f64 {
+        match grade {
+            Grade::Again => 1.0,
+            Grade::Hard => 3.0,
+            Grade::Good => 4.0,
+            Grade::Easy => 5.0,
+        }
+    }
+
+    fn ease_factor(ease_factor: f64, grade: Grade) -> f64 {
+        let q = Sm2::quality(grade);
+        let ef = ease_factor + (0.1 - (5.0 - q) * (0.08 + (5.0 - q) * 0.02));
+        ef.max(MIN_EASE_FACTOR)
+    }
+
+    fn interval(state: &ScheduleState, ease_factor: f64) -> i64 {
+        match state.repetitions {
+            0 => DAY_SECS,
+            1 => 6 * DAY_SECS,
+            _ => (state.interval_secs as f64 * ease_factor).round() as i64,
+        }
+    }
+}
+
+impl SchedulingAlgorithm for Sm2 {
+    fn after_delivery(&self, state: &ScheduleState, now: i64) -> ScheduleState {
+        let interval = Sm2::interval(state, state.ease_factor);
+        ScheduleState {
+            next_run: Some(now + interval),
+            interval_secs: interval,
+            repetitions: state.repetitions + 1,
+            ..state.clone()
+        }
+    }
+
+    fn after_review(&self, state: &ScheduleState, grade: Grade, now: i64) -> ScheduleState {
+        let ease_factor = Sm2::ease_factor(state.ease_factor, grade);
+        let (interval, repetitions) = match grade {
+            Grade::Good => return state.clone(),
+            // learn it anew, next repetition is the first one
+            Grade::Again => (DAY_SECS, 1),
+            Grade::Hard => (state.interval_secs, state.repetitions),
+            Grade::Easy => (Sm2::interval(state, ease_factor), state.repetitions + 1),
+        };
+
+        ScheduleState {
+            next_run: Some(now + interval),
+            ease_factor,
+            interval_secs: interval,
+            repetitions,
+            ..state.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deliveries_follow_sm2_intervals() {
+        let first = Sm2.after_delivery(&ScheduleState::new(1, 0), 0);
+        assert_eq!(first.next_run, Some(DAY_SECS));
+        let second = Sm2.after_delivery(&first, DAY_SECS);
+        assert_eq!(second.next_run, Some(7 * DAY_SECS));
+        let third = Sm2.after_delivery(&second, 7 * DAY_SECS);
+        assert_eq!(third.interval_secs, 15 * DAY_SECS);
+        assert_eq!(third.repetitions, 3);
+    }
+
+    #[test]
+    fn again_restarts_and_lowers_ease() {
+        let state = Sm2.after_delivery(&Sm2.after_delivery(&ScheduleState::new(1, 0), 0), DAY_SECS);
+        let reviewed = Sm2.after_review(&state, Grade::Again, 2 * DAY_SECS);
+        assert_eq!(reviewed.next_run, Some(3 * DAY_SECS));
+        assert_eq!(reviewed.repetitions, 1);
+        assert!((reviewed.ease_factor - 1.96).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ease_factor_has_lower_bound() {
+        let mut state = ScheduleState::new(1, 0);
+        for _ in 0..10 {
+            state = Sm2.after_review(&state, Grade::Again, 0);
+        }
+        assert!((state.ease_factor - MIN_EASE_FACTOR).abs() < 1e-9);
+    }
+}
@@ -1,9 +1,39 @@
+table! {
+    api_keys (id) {
+        id -> Int4,
+        user_id -> Int4,
+        key_hash -> Varchar,
+        created_at -> Int8,
+    }
+}
+
+table! {
+    decks (id) {
+        id -> Int4,
+        user_id -> Int4,
+        name -> Varchar,
+        phase_set -> Nullable<Varchar>,
+        notifications_enabled -> Bool,
+    }
+}
+
 table! {
     memories (id) {
         id -> Int4,
         user_id -> Int4,
         topic -> Nullable<Varchar>,
         text -> Text,
+        algorithm -> Nullable<Varchar>,
+        deck_id -> Nullable<Int4>,
+        format -> Varchar,
+        back -> Nullable<Text>,
+    }
+}
+
+table! {
+    memory_tags (memory_id, tag_id) {
+        memory_id -> Int4,
+        tag_id -> Int4,
     }
 }
 
@@ -12,6 +42,20 @@ table! {
         id -> Int4,
         phase_number -> Int4,
         seconds_to_wait -> Int8,
+        phase_set -> Varchar,
+    }
+}
+
+table! {
+    reviews (id) {
+        id -> Int4,
+        schedule_id -> Int4,
+        phase_number -> Int4,
+        sent_at -> Int8,
+        channel -> Varchar,
+        delivery_status -> Varchar,
+        grade -> Nullable<Varchar>,
+        response_latency -> Nullable<Int8>,
     }
 }
 
@@ -21,6 +65,25 @@ table! {
         memory_id -> Int4,
         phase_number -> Int4,
         next_run -> Nullable<Int8>,
+        ease_factor -> Float8,
+        interval_secs -> Int8,
+        repetitions -> Int4,
+        stability -> Nullable<Float8>,
+        difficulty -> Nullable<Float8>,
+        locked_by -> Nullable<Varchar>,
+        locked_until -> Nullable<Int8>,
+        status -> Varchar,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        card -> Int4,
+    }
+}
+
+table! {
+    tags (id) {
+        id -> Int4,
+        user_id -> Int4,
+        name -> Varchar,
     }
 }
 
@@ -28,15 +91,46 @@ table! {
     users (id) {
         id -> Int4,
         email -> Varchar,
+        algorithm -> Varchar,
+        timezone -> Varchar,
+        quiet_start -> Nullable<Time>,
+        quiet_end -> Nullable<Time>,
+        delivery_mode -> Varchar,
+        digest_time -> Nullable<Time>,
+        phase_set -> Nullable<Varchar>,
+        verified -> Bool,
+        unsubscribed -> Bool,
+    }
+}
+
+table! {
+    verification_tokens (user_id) {
+        user_id -> Int4,
+        token_hash -> Varchar,
+        created_at -> Int8,
     }
 }
 
+joinable!(api_keys -> users (user_id));
+joinable!(decks -> users (user_id));
+joinable!(memories -> decks (deck_id));
 joinable!(memories -> users (user_id));
+joinable!(memory_tags -> memories (memory_id));
+joinable!(memory_tags -> tags (tag_id));
+joinable!(reviews -> schedules (schedule_id));
 joinable!(schedules -> memories (memory_id));
+joinable!(tags -> users (user_id));
+joinable!(verification_tokens -> users (user_id));
 
 allow_tables_to_appear_in_same_query!(
+    api_keys,
+    decks,
     memories,
+    memory_tags,
     phases,
+    reviews,
     schedules,
+    tags,
     users,
+    verification_tokens,
 );
@@ -0,0 +1,330 @@
+use crate::links::Links;
+use crate::markdown::{Markdown, TextFormat};
+use crate::models::User;
+use crate::quiet_hours;
+use crate::scheduling::Grade;
+use chrono::TimeZone;
+use chrono_tz::Tz;
+use handlebars::Handlebars;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SUBJECT: &str = "reminder.subject";
+const TEXT: &str = "reminder.txt";
+const HTML: &str = "reminder.html";
+const REVEAL: &str = "reveal.html";
+
+/// Length of memory text excerpt used in subject when memory has no topic.
+const SUMMARY_LEN: usize = 60;
+
+#[derive(Debug, Fail)]
+pub enum TemplateError {
+    #[fail(display = "fail to read template '{}'", path)]
+    Unreadable { path: String },
+    #[fail(display = "invalid template '{}': {}", name, reason)]
+    Invalid { name: &'static str, reason: String },
+}
+
+/// Links that grade a card in one click, posted by buttons of reveal page.
+#[derive(Serialize, Debug)]
+pub struct GradeLinks {
+    pub again: String,
+    pub hard: String,
+    pub good: String,
+    pub easy: String,
+}
+
+impl GradeLinks {
+    pub fn new(links: &Links, schedule_id: i32) -> GradeLinks {
+        GradeLinks {
+            again: links.grade_card(schedule_id, Grade::Again),
+            hard: links.grade_card(schedule_id, Grade::Hard),
+            good: links.grade_card(schedule_id, Grade::Good),
+            easy: links.grade_card(schedule_id, Grade::Easy),
+        }
+    }
+}
+
+/// One memory in a reminder.
+#[derive(Serialize, Debug)]
+pub struct MemoryContext {
+    pub topic: Option<String>,
+    pub text: String,
+    /// Text rendered to HTML when it is written in markdown.
+    pub text_html: Option<String>,
+    pub phase: i32,
+    pub total_phases: usize,
+    pub deck: Option<String>,
+    /// Time of the next reminder in user's timezone, `None` after the last one.
+    pub next_review: Option<String>,
+    pub grade_links: GradeLinks,
+    /// Link to the answer of a card, its reminder shows only the question.
+    pub reveal_link: Option<String>,
+    pub pause_link: String,
+}
+
+/// Variables available to reminder templates.
+#[derive(Serialize, Debug)]
+pub struct ReminderContext {
+    /// Several memories are sent in one message.
+    pub digest: bool,
+    pub count: usize,
+    /// Topic of the only memory of a message.
+    pub topic: Option<String>,
+    /// Beginning of the first memory text.
+    pub summary: String,
+    pub memories: Vec<MemoryContext>,
+    pub unsubscribe_link: String,
+}
+
+impl ReminderContext {
+    pub fn new(memories: Vec<MemoryContext>, unsubscribe_link: String) -> ReminderContext {
+        let topic = match memories.as_slice() {
+            [only] => only.topic.clone(),
+            _ => None,
+        };
+        let summary = memories
+            .first()
+            .map(|m| summary(&m.text))
+            .unwrap_or_default();
+
+        ReminderContext {
+            digest: memories.len() > 1,
+            count: memories.len(),
+            topic,
+            summary,
+            memories,
+            unsubscribe_link,
+        }
+    }
+}
+
+/// Variables available to the page with the answer of a card.
+#[derive(Serialize, Debug)]
+pub struct RevealContext {
+    pub topic: Option<String>,
+    pub front: String,
+    pub front_html: Option<String>,
+    pub back: String,
+    pub back_html: Option<String>,
+    pub grade_links: GradeLinks,
+}
+
+pub struct RenderedEmail {
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+/// Reminder templates: built in ones from `templates` directory of the project,
+/// each of them may be replaced by a file with the same name in `TEMPLATES_DIR`.
+pub struct Templates {
+    text: Handlebars<'static>,
+    html: Handlebars<'static>,
+    markdown: Markdown,
+}
+
+impl Templates {
+    pub fn new(dir: Option<&Path>) -> Result<Templates, TemplateError> {
+        let mut text = Handlebars::new();
+        text.register_escape_fn(handlebars::no_escape);
+        register(
+            &mut text,
+            SUBJECT,
+            dir,
+            include_str!("../templates/reminder.subject.hbs"),
+        )?;
+        register(
+            &mut text,
+            TEXT,
+            dir,
+            include_str!("../templates/reminder.txt.hbs"),
+        )?;
+
+        let mut html = Handlebars::new();
+        register(
+            &mut html,
+            HTML,
+            dir,
+            include_str!("../templates/reminder.html.hbs"),
+        )?;
+        register(
+            &mut html,
+            REVEAL,
+            dir,
+            include_str!("../templates/reveal.html.hbs"),
+        )?;
+
+        Ok(Templates {
+            text,
+            html,
+            markdown: Markdown::new(),
+        })
+    }
+
+    pub fn from_env() -> Result<Templates, TemplateError> {
+        let dir = env::var("TEMPLATES_DIR").ok().map(PathBuf::from);
+        Templates::new(dir.as_deref())
+    }
+
+    /// HTML of memory text written in `format`, `None` for plain text.
+    pub fn text_html(&self, text: &str, format: &str) -> Option<String> {
+        match format.parse() {
+            Ok(TextFormat::Markdown) => Some(self.markdown.to_html(text)),
+            _ => None,
+        }
+    }
+
+    pub fn render(&self, context: &ReminderContext) -> Result<RenderedEmail, failure::Error> {
+        let subject = self.text.render(SUBJECT, context)?;
+
+        Ok(RenderedEmail {
+            // line breaks are not allowed in subject
+            subject: subject.split_whitespace().collect::<Vec<_>>().join(" "),
+            text: self.text.render(TEXT, context)?,
+            html: self.html.render(HTML, context)?,
+        })
+    }
+
+    pub fn render_reveal(&self, context: &RevealContext) -> Result<String, failure::Error> {
+        Ok(self.html.render(REVEAL, context)?)
+    }
+}
+
+fn register(
+    registry: &mut Handlebars,
+    name: &'static str,
+    dir: Option<&Path>,
+    default: &str,
+) -> Result<(), TemplateError> {
+    let overridden = dir
+        .map(|d| d.join(format!("{}.hbs", name)))
+        .filter(|path| path.exists());
+    let source = match overridden {
+        Some(path) => fs::read_to_string(&path).map_err(|_| TemplateError::Unreadable {
+            path: path.display().to_string(),
+        })?,
+        None => default.to_string(),
+    };
+
+    registry
+        .register_template_string(name, source)
+        .map_err(|err| TemplateError::Invalid {
+            name,
+            reason: err.to_string(),
+        })
+}
+
+/// First line of `text` cut to `SUMMARY_LEN` characters.
+fn summary(text: &str) -> String {
+    let line = text
+        .lines()
+        .find(|l| !l.trim().is_empty())
+        .unwrap_or("")
+        .trim();
+    if line.chars().count() > SUMMARY_LEN {
+        let cut: String = line.chars().take(SUMMARY_LEN).collect();
+        format!("{}…", cut.trim_end())
+    } else {
+        line.to_string()
+    }
+}
+
+/// Time in user's timezone, UTC when the timezone is unknown.
+pub fn local_time(at_secs: i64, user: &User) -> String {
+    let timezone = quiet_hours::parse_timezone(&user.timezone).unwrap_or(Tz::UTC);
+    timezone
+        .timestamp(at_secs, 0)
+        .format("%Y-%m-%d %H:%M %Z")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory(topic: Option<&str>, text: &str) -> MemoryContext {
+        MemoryContext {
+            topic: topic.map(|t| t.to_string()),
+            text: text.to_string(),
+            text_html: None,
+            phase: 2,
+            total_phases: 7,
+            deck: Some("Rust".to_string()),
+            next_review: Some("2020-04-21 09:00 MSK".to_string()),
+            grade_links: GradeLinks {
+                again: "http://localhost/again".to_string(),
+                hard: "http://localhost/hard".to_string(),
+                good: "http://localhost/good".to_string(),
+                easy: "http://localhost/easy".to_string(),
+            },
+            reveal_link: None,
+            pause_link: "http://localhost/pause".to_string(),
+        }
+    }
+
+    #[test]
+    fn default_templates_render_all_parts() {
+        let templates = Templates::new(None).unwrap();
+        let context = ReminderContext::new(
+            vec![memory(None, "Vec<T> owns\nits elements")],
+            "http://localhost/unsubscribe".to_string(),
+        );
+        let email = templates.render(&context).unwrap();
+
+        assert_eq!(email.subject, "Time to repeat: Vec<T> owns");
+        assert!(email.text.contains("Vec<T> owns\nits elements"));
+        assert!(email.text.contains("Repetition 2 of 7"));
+        assert!(email.text.contains("http://localhost/unsubscribe"));
+        assert!(email.html.contains("Vec&lt;T&gt; owns"));
+        assert!(email.html.contains("href=\"http://localhost/good\""));
+    }
+
+    #[test]
+    fn markdown_is_put_into_html_part_as_is() {
+        let templates = Templates::new(None).unwrap();
+        let mut markdown = memory(None, "**bold**");
+        markdown.text_html = Some("<p><strong>bold</strong></p>".to_string());
+        let context =
+            ReminderContext::new(vec![markdown], "http://localhost/unsubscribe".to_string());
+        let email = templates.render(&context).unwrap();
+
+        assert!(email.html.contains("<p><strong>bold</strong></p>"));
+        assert!(email.text.contains("**bold**"));
+    }
+
+    #[test]
+    fn card_shows_reveal_link_instead_of_grades() {
+        let templates = Templates::new(None).unwrap();
+        let mut card = memory(None, "[...] checks borrows");
+        card.reveal_link = Some("http://localhost/reveal".to_string());
+        let context = ReminderContext::new(vec![card], "http://localhost/unsubscribe".to_string());
+        let email = templates.render(&context).unwrap();
+
+        assert!(email.text.contains("http://localhost/reveal"));
+        assert!(!email.text.contains("http://localhost/good"));
+        assert!(email.html.contains("href=\"http://localhost/reveal\""));
+        assert!(!email.html.contains("http://localhost/good"));
+    }
+
+    #[test]
+    fn digest_subject_counts_memories() {
+        let templates = Templates::new(None).unwrap();
+        let context = ReminderContext::new(
+            vec![memory(Some("a"), "b"), memory(Some("c"), "d")],
+            "http://localhost/unsubscribe".to_string(),
+        );
+        assert_eq!(
+            templates.render(&context).unwrap().subject,
+            "2 memories to repeat"
+        );
+    }
+
+    #[test]
+    fn long_text_is_cut_in_summary() {
+        let text = "a".repeat(SUMMARY_LEN + 10);
+        assert_eq!(summary(&text).chars().count(), SUMMARY_LEN + 1);
+        assert_eq!(summary("\n  short  \nrest"), "short");
+    }
+}
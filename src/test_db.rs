@@ -0,0 +1,89 @@
+//! Database of tests marked `#[ignore]`, they are run with `cargo test -- --ignored`
+//! against migrated `DATABASE_URL`. Changes are made in a test transaction and never committed,
+//! unless a test needs several connections to see the same rows.
+
+use crate::auth;
+use crate::db_actions;
+use crate::delivery::DeliveryMode;
+use crate::models;
+use crate::scheduling::AlgorithmKind;
+use crate::DbPool;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::r2d2::{self, ConnectionManager, CustomizeConnection};
+
+fn database_url() -> String {
+    dotenv::dotenv().ok();
+    std::env::var("DATABASE_URL").expect("DATABASE_URL must be set")
+}
+
+/// Connection which changes are rolled back when it is dropped.
+pub fn connection() -> PgConnection {
+    let conn = PgConnection::establish(&database_url()).expect("couldn't connect to db");
+    conn.begin_test_transaction()
+        .expect("couldn't begin test transaction");
+    conn
+}
+
+/// Connection which commits its changes, for tests that need several connections to see the same rows.
+pub fn committing_connection() -> PgConnection {
+    PgConnection::establish(&database_url()).expect("couldn't connect to db")
+}
+
+#[derive(Debug)]
+struct TestTransaction;
+
+impl CustomizeConnection<PgConnection, r2d2::Error> for TestTransaction {
+    fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), r2d2::Error> {
+        conn.begin_test_transaction()
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
+/// Pool of one connection, so every request sees changes of the previous ones and nothing is committed.
+pub fn pool() -> DbPool {
+    r2d2::Pool::builder()
+        .max_size(1)
+        .connection_customizer(Box::new(TestTransaction))
+        .build(ConnectionManager::<PgConnection>::new(database_url()))
+        .expect("couldn't build db pool")
+}
+
+/// Verified user, returned with its API key.
+pub fn user(email: &str, conn: &PgConnection) -> (i32, String) {
+    use crate::schema::users;
+
+    let (key, key_hash) = auth::new_secret();
+    let new_user = models::NewUser {
+        email,
+        algorithm: AlgorithmKind::Fixed.as_str(),
+        timezone: "UTC",
+        quiet_start: None,
+        quiet_end: None,
+        delivery_mode: DeliveryMode::Immediate.as_str(),
+        digest_time: None,
+        phase_set: None,
+    };
+    let user_id = db_actions::insert_user(&new_user, &key_hash, conn).expect("couldn't add user");
+    diesel::update(users::table.filter(users::id.eq(user_id)))
+        .set(users::verified.eq(true))
+        .execute(conn)
+        .expect("couldn't verify user");
+
+    (user_id, key)
+}
+
+/// Plain memory of user, its only card is due right away.
+pub fn memory(user_id: i32, text: &str, conn: &PgConnection) -> i32 {
+    let phase_sets = db_actions::get_phase_sets(conn).expect("couldn't load phase sets");
+    let new_memory = models::NewMemory {
+        user_id,
+        topic: None,
+        text,
+        algorithm: None,
+        deck_id: None,
+        format: "plain",
+        back: None,
+    };
+    db_actions::insert_reminder(&new_memory, &[], &phase_sets, conn).expect("couldn't add memory")
+}